@@ -43,7 +43,7 @@ pub fn put_bench(c: &mut Criterion) {
         })
     });
     group.bench_function("Dashmap", |b| {
-        let mut hashtable = dashmap::DashMap::<u64, u64>::new();
+        let hashtable = dashmap::DashMap::<u64, u64>::new();
         b.iter(|| {
             let key = black_box(10);
             let value = black_box(10);
@@ -90,7 +90,7 @@ pub fn get_bench(c: &mut Criterion) {
         })
     });
     group.bench_function("Dashmap", |b| {
-        let mut hashtable = dashmap::DashMap::<u64, u64>::new();
+        let hashtable = dashmap::DashMap::<u64, u64>::new();
         let key = black_box(10);
         let value = black_box(10);
         hashtable.insert(key, value);
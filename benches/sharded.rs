@@ -27,7 +27,7 @@ use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::hint::black_box;
 use std::sync::{Arc, Mutex};
-use std::{thread, u32};
+use std::thread;
 
 #[derive(Debug, Clone, Copy)]
 struct Object([u8; 47]);
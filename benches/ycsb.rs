@@ -28,6 +28,7 @@ use std::hint::black_box;
 
 const NUM_OPS: usize = 10_000;
 const KEY_SPACE: u64 = 1000;
+const BATCH_SIZE: usize = 32;
 
 /// YCSB Workload A: 50% GET, 50% PUT
 pub fn workload_a(c: &mut Criterion) {
@@ -167,6 +168,27 @@ pub fn workload_c(c: &mut Criterion) {
         );
     });
 
+    group.bench_function("CacheTable batched", |b| {
+        b.iter_batched(
+            || {
+                let cache = CacheTable::<u64, Vec<u32>, 4, 32>::new();
+                for i in 0..KEY_SPACE {
+                    cache.insert(i, vec![i as u32]);
+                }
+                let rng = StdRng::seed_from_u64(42);
+                (cache, rng)
+            },
+            |(cache, mut rng)| {
+                let mut keys = [0; BATCH_SIZE];
+                for _ in 0..NUM_OPS / BATCH_SIZE {
+                    keys.fill_with(|| rng.random_range(0..KEY_SPACE));
+                    black_box(cache.get_many(&keys));
+                }
+            },
+            criterion::BatchSize::SmallInput,
+        );
+    });
+
     group.bench_function("HashTable", |b| {
         b.iter_batched(
             || {
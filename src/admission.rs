@@ -0,0 +1,183 @@
+/* admission.rs --- ADMISSION
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/// A TinyLFU admission filter: a new key only gets into a full `CacheTable`
+/// if it has been seen more often than the entry it would evict.
+///
+/// Key frequencies are estimated from their hashes with a count-min sketch
+/// of 4-bit counters. The first sighting of a key only sets its bits in a
+/// small bloom filter, the doorkeeper, so keys seen once never reach the
+/// sketch. After a sample of as many accesses as ten times the capacity, every
+/// counter is halved and the doorkeeper is cleared, so old popularity fades.
+#[derive(Debug, Clone)]
+pub struct TinyLfu {
+    sketch: Box<[u8]>,
+    width_mask: usize,
+    doorkeeper: Box<[u64]>,
+    door_mask: usize,
+    additions: usize,
+    sample_size: usize,
+}
+
+impl TinyLfu {
+    /// The number of rows of the sketch, each indexed by its own hash.
+    const DEPTH: usize = 4;
+    /// The largest count a counter holds.
+    const MAX_COUNT: u8 = 15;
+    /// The seeds mixed into the key hash to index each row of the sketch.
+    const SEEDS: [u64; Self::DEPTH] = [
+        0xc3a5_c85c_97cb_3127,
+        0xb492_b66f_be98_f273,
+        0x9ae1_6a3b_2f90_404f,
+        0xcbf2_9ce4_8422_2325,
+    ];
+
+    /// Creates a filter sized for a table holding about `capacity` entries.
+    ///
+    /// # Arguments
+    /// * `capacity` - The number of entries of the table, usually its
+    ///   `capacity()`.
+    ///
+    /// # Panics
+    /// If `capacity` is 0.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity must not be 0");
+        let width = capacity.next_power_of_two();
+        let door_bits = (capacity * 8).next_power_of_two().max(64);
+        Self {
+            sketch: vec![0; Self::DEPTH * width].into_boxed_slice(),
+            width_mask: width - 1,
+            doorkeeper: vec![0; door_bits / 64].into_boxed_slice(),
+            door_mask: door_bits - 1,
+            additions: 0,
+            sample_size: capacity * 10,
+        }
+    }
+
+    /// Returns the position of the counter of `hash` in `row` of the sketch.
+    #[inline]
+    fn counter(&self, row: usize, hash: u64) -> usize {
+        let mixed = (hash ^ Self::SEEDS[row]).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        row * (self.width_mask + 1) + ((mixed >> 32) as usize & self.width_mask)
+    }
+
+    /// Returns the two doorkeeper bits of `hash`.
+    #[inline]
+    fn door_bits(&self, hash: u64) -> [usize; 2] {
+        let mixed = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        [
+            mixed as usize & self.door_mask,
+            (mixed >> 32) as usize & self.door_mask,
+        ]
+    }
+
+    /// Checks whether every doorkeeper bit of `hash` is set.
+    #[inline]
+    fn door_contains(&self, hash: u64) -> bool {
+        self.door_bits(hash)
+            .iter()
+            .all(|&bit| self.doorkeeper[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Records one access to the key hashing to `hash`.
+    pub(crate) fn record(&mut self, hash: u64) {
+        if self.door_contains(hash) {
+            for row in 0..Self::DEPTH {
+                let counter = self.counter(row, hash);
+                self.sketch[counter] = (self.sketch[counter] + 1).min(Self::MAX_COUNT);
+            }
+        } else {
+            for bit in self.door_bits(hash) {
+                self.doorkeeper[bit / 64] |= 1 << (bit % 64);
+            }
+        }
+        self.additions += 1;
+        if self.additions == self.sample_size {
+            self.age();
+        }
+    }
+
+    /// Halves every counter and clears the doorkeeper.
+    fn age(&mut self) {
+        self.sketch.iter_mut().for_each(|count| *count /= 2);
+        self.doorkeeper.iter_mut().for_each(|word| *word = 0);
+        self.additions /= 2;
+    }
+
+    /// Returns the estimated number of recent accesses to the key hashing to
+    /// `hash`. The estimate may be too high, never too low.
+    pub fn frequency(&self, hash: u64) -> u8 {
+        let count = (0..Self::DEPTH)
+            .map(|row| self.sketch[self.counter(row, hash)])
+            .min()
+            .unwrap_or(0);
+        count + self.door_contains(hash) as u8
+    }
+
+    /// Decides whether the key hashing to `candidate` may evict the entry
+    /// whose key hashes to `victim`. Ties favour the victim, which is cached
+    /// already.
+    pub(crate) fn admit(&self, candidate: u64, victim: u64) -> bool {
+        self.frequency(candidate) > self.frequency(victim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TinyLfu;
+
+    /// Tests that the doorkeeper absorbs the first sighting and the sketch
+    /// counts the rest, saturating at 15.
+    #[test]
+    fn counts() {
+        let mut lfu = TinyLfu::new(1024);
+        assert_eq!(lfu.frequency(7), 0);
+        lfu.record(7);
+        assert_eq!(lfu.frequency(7), 1);
+        for _ in 0..3 {
+            lfu.record(7);
+        }
+        assert_eq!(lfu.frequency(7), 4);
+        for _ in 0..20 {
+            lfu.record(7);
+        }
+        assert_eq!(lfu.frequency(7), 16);
+
+        assert!(lfu.admit(7, 8));
+        assert!(!lfu.admit(8, 7));
+        assert!(!lfu.admit(8, 9));
+    }
+
+    /// Tests that counts are halved and the doorkeeper is cleared once a
+    /// sample is complete.
+    #[test]
+    fn ages() {
+        let mut lfu = TinyLfu::new(1);
+        for _ in 0..9 {
+            lfu.record(7);
+        }
+        assert_eq!(lfu.frequency(7), 9);
+        lfu.record(7);
+        assert_eq!(lfu.frequency(7), 4);
+    }
+}
+
+/* admission.rs ends here */
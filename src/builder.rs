@@ -0,0 +1,268 @@
+/* builder.rs --- BUILDER
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::cachetable::{DynCacheTable, InnerCache};
+use crate::hash::WyHashBuilder;
+use crate::policy::{Fifo, Policy};
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hash};
+
+/// The reasons a `CacheTableBuilder` can refuse to build a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// The log size is not a power of two.
+    LogSize(usize),
+    /// The number of sets is not a power of two.
+    SetCount(usize),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::LogSize(size) => {
+                write!(f, "Log size must be a power of two, got {size}!")
+            }
+            BuildError::SetCount(count) => {
+                write!(f, "Set size must be a power of two, got {count}!")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// A builder for `DynCacheTable`, whose log size and number of sets are read
+/// at runtime, for instance from a configuration file.
+///
+/// Both sizes must be powers of two, as with the const parameters of
+/// `CacheTable`, but a bad size is reported as a `BuildError` instead of a
+/// panic.
+///
+/// # Example
+/// ```
+/// use cachetable::CacheTableBuilder;
+///
+/// let ctable = CacheTableBuilder::new()
+///     .log_size(1 << 10)
+///     .sets(1 << 6)
+///     .build::<u64, String>()
+///     .unwrap();
+/// ctable.insert(10, "ten".to_string());
+/// assert_eq!(ctable.get(&10).as_deref(), Some("ten"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CacheTableBuilder<S = WyHashBuilder, P = Fifo> {
+    log_size: usize,
+    sets: usize,
+    hasher: S,
+    make_hasher: fn(&S) -> S,
+    policy: P,
+}
+
+impl CacheTableBuilder {
+    /// Creates a builder for a table with a one-entry log and a single set.
+    ///
+    /// Every table it builds gets its own randomly seeded hasher, unless
+    /// `seed` or `hasher` picks one.
+    pub fn new() -> Self {
+        Self {
+            log_size: 1,
+            sets: 1,
+            hasher: WyHashBuilder::default(),
+            make_hasher: |_| WyHashBuilder::new(),
+            policy: Fifo::default(),
+        }
+    }
+}
+
+impl<P: Policy> CacheTableBuilder<WyHashBuilder, P> {
+    /// Seeds the hasher of every table built with `seed` instead of a random
+    /// seed.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.hasher = WyHashBuilder::with_seed(seed);
+        self.make_hasher = WyHashBuilder::clone;
+        self
+    }
+}
+
+impl<S: BuildHasher, P: Policy> CacheTableBuilder<S, P> {
+    /// Replaces the `BuildHasher` the table places keys with. Every table
+    /// built gets a clone of `hasher`.
+    pub fn hasher<H: BuildHasher + Clone>(self, hasher: H) -> CacheTableBuilder<H, P> {
+        CacheTableBuilder {
+            log_size: self.log_size,
+            sets: self.sets,
+            hasher,
+            make_hasher: H::clone,
+            policy: self.policy,
+        }
+    }
+
+    /// Replaces the `Policy` the table evicts from full sets with.
+    pub fn policy<Q: Policy>(self, policy: Q) -> CacheTableBuilder<S, Q> {
+        CacheTableBuilder {
+            log_size: self.log_size,
+            sets: self.sets,
+            hasher: self.hasher,
+            make_hasher: self.make_hasher,
+            policy,
+        }
+    }
+
+    /// Sets the number of entries in the log, which must be a power of two.
+    pub fn log_size(mut self, log_size: usize) -> Self {
+        self.log_size = log_size;
+        self
+    }
+
+    /// Sets the number of sets, which must be a power of two.
+    pub fn sets(mut self, sets: usize) -> Self {
+        self.sets = sets;
+        self
+    }
+
+    /// Allocates the table on the heap.
+    ///
+    /// # Returns
+    /// The new table, or a `BuildError` naming the size that is not a power of two.
+    pub fn build<K: Hash + Eq, V>(&self) -> Result<DynCacheTable<K, V, S, P>, BuildError>
+    where
+        P: Clone,
+    {
+        InnerCache::new(self.log_size, self.sets, self.policy.clone())
+            .map(|inner| DynCacheTable::from_inner(inner, (self.make_hasher)(&self.hasher)))
+    }
+}
+
+impl Default for CacheTableBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BuildError, CacheTableBuilder};
+    use crate::{Insertion, Lru, WyHashBuilder};
+    use smallvec::smallvec;
+
+    /// Tests building a table sized at runtime and using it.
+    #[test]
+    fn build() {
+        let ctable = CacheTableBuilder::new()
+            .log_size(8)
+            .sets(4)
+            .build::<u32, u32>()
+            .unwrap();
+        assert_eq!(ctable.capacity(), 8);
+
+        for key in 0..9 {
+            ctable.insert(key, key);
+        }
+        assert_eq!(ctable.len(), 8);
+        assert_eq!(ctable.get(&0), None);
+        assert_eq!(ctable.get(&8), Some(8));
+    }
+
+    /// Tests that sizes that are not powers of two are rejected.
+    #[test]
+    fn build_rejects_sizes() {
+        let builder = CacheTableBuilder::new();
+        assert_eq!(
+            builder.clone().log_size(6).build::<u32, u32>().err(),
+            Some(BuildError::LogSize(6))
+        );
+        assert_eq!(
+            builder.clone().sets(0).build::<u32, u32>().err(),
+            Some(BuildError::SetCount(0))
+        );
+    }
+
+    /// Tests that the builder hands its hasher to the table.
+    #[test]
+    fn build_with_hasher() {
+        let ctable = CacheTableBuilder::new()
+            .log_size(8)
+            .sets(4)
+            .hasher(WyHashBuilder::with_seed(7))
+            .build::<u32, u32>()
+            .unwrap();
+        assert_eq!(ctable.hasher().seed(), 7);
+        assert_eq!(
+            CacheTableBuilder::new()
+                .seed(7)
+                .build::<u32, u32>()
+                .unwrap()
+                .hasher(),
+            ctable.hasher()
+        );
+
+        ctable.insert(1, 10);
+        assert_eq!(ctable.get(&1), Some(10));
+    }
+
+    /// Tests that every table built without a seed gets its own random seed.
+    #[test]
+    fn build_reseeds() {
+        let builder = CacheTableBuilder::new().log_size(8).sets(4);
+        let first = builder.build::<u32, u32>().unwrap();
+        let second = builder.build::<u32, u32>().unwrap();
+        assert_ne!(first.hasher().seed(), second.hasher().seed());
+
+        let builder = builder.seed(7);
+        assert_eq!(builder.build::<u32, u32>().unwrap().hasher().seed(), 7);
+        assert_eq!(builder.build::<u32, u32>().unwrap().hasher().seed(), 7);
+    }
+
+    /// Tests that the builder hands its policy to the table.
+    #[test]
+    fn build_with_policy() {
+        let ctable = CacheTableBuilder::new()
+            .log_size(64)
+            .sets(1)
+            .policy(Lru::default())
+            .build::<u32, u32>()
+            .unwrap();
+
+        for key in 0..16 {
+            ctable.insert(key, key);
+        }
+        ctable.get(&0);
+        assert_eq!(ctable.insert(16, 16), Insertion::Evicted(smallvec![(1, 1)]));
+    }
+
+    /// Tests that a large log with large values is built without going
+    /// through the stack.
+    #[test]
+    fn build_large() {
+        struct Page([u8; 4096]);
+
+        let ctable = CacheTableBuilder::new()
+            .log_size(1 << 12)
+            .sets(1 << 8)
+            .build::<u32, Page>()
+            .unwrap();
+        ctable.insert(1, Page([1; 4096]));
+        assert_eq!(ctable.get_with(&1, |page| page.0[4095]), Some(1));
+    }
+}
+
+/* builder.rs ends here */
@@ -19,125 +19,760 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use crate::set::Set;
+use crate::admission::TinyLfu;
+use crate::builder::BuildError;
+use crate::entry::{Entry, OccupiedEntry, VacantEntry};
+use crate::hash::WyHashBuilder;
+use crate::iter::{Drain, Iter, IterOrder, Keys, Values};
+use crate::listener::{Listener, RemovalCause};
+use crate::occupancy::Occupancy;
+use crate::policy::{Fifo, Policy, Reclaim};
+use crate::prefetch::prefetch;
+use crate::set::{Set, EMPTY_FINGER, SIMD_SIZE};
+use crate::time::{MonotonicTime, TimeSource};
+use crate::weigher::{Budget, Weigher};
+use crate::wheel::TimingWheel;
 use crate::{kv::LogItem, log::Log};
+use smallvec::SmallVec;
+use std::borrow::Borrow;
 use std::cell::RefCell;
-use std::hash::{Hash, Hasher};
-use wyhash2::WyHash;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+/// The entries an insert displaced, in the order they left the table.
+///
+/// An insert evicts at most the oldest entry of the log and the victim of a
+/// full set, which are kept inline so that inserting into a full table does
+/// not allocate. Only a weight budget can evict more and spill to the heap.
+pub type Evictions<K, V> = SmallVec<[(K, V); 2]>;
+
+/// The outcome of inserting a key-value pair into a `CacheTable`.
+///
+/// Displaced keys and values are moved out of the table, never cloned, so the
+/// caller can write them back to an origin store or drop them.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Insertion<K, V> {
+    /// The key was not cached and nothing had to make room for it.
+    Inserted,
+    /// The key was cached. With a weight budget, a heavier value may have
+    /// evicted the oldest other entries to fit.
+    Updated {
+        /// The value the new one replaced.
+        old: V,
+        /// The entries evicted to keep the weight budget.
+        evicted: Evictions<K, V>,
+    },
+    /// The key was not cached and the listed entries were evicted to make
+    /// room for it: the oldest live entry in the log once it wraps, the
+    /// victim of the key's set when all of its slots were taken, and with a
+    /// weight budget the oldest entries evicted until the new one fits.
+    Evicted(Evictions<K, V>),
+    /// The key was turned away, either by the admission filter in favour of
+    /// the entry it would have evicted, or because it weighs more than the
+    /// whole weight budget; holds the key and value. In the latter case a
+    /// value already cached for the key is removed, so it is not read stale.
+    Rejected(K, V),
+}
+
+/// The outcome of looking a key up in an `InnerCache`.
+pub(crate) enum Lookup<'a, V> {
+    /// The key is cached and has not expired.
+    Hit(&'a V),
+    /// The key is cached in `slot` of `set` but has expired, so it reads as
+    /// a miss; the caller reclaims the slot.
+    Expired(usize, usize),
+    /// The key is not cached.
+    Miss,
+}
 
 /// The `InnerCache` struct is responsible for managing the internal structure of the cache.
 /// It uses sets to organize cache entries and maintains a log for storing key-value pairs.
-struct InnerCache<K, V, const L: usize, const S: usize> {
-    sets: [Set; S],
-    log: Log<K, V, L>,
+///
+/// The policy sits in its own `RefCell` so lookups, which only borrow the
+/// cache, can still report hits to it.
+pub(crate) struct InnerCache<K, V, P> {
+    pub(crate) sets: Box<[Set]>,
+    pub(crate) log: Log<K, V>,
     set_mask: usize,
-    log_mask: usize,
-    log_head: usize,
+    len: usize,
+    generation: u32,
+    listener: Option<Listener<K, V>>,
+    policy: RefCell<P>,
+    admission: Option<RefCell<TinyLfu>>,
+    budget: Option<Budget<K, V>>,
+    weight: u64,
+    time: Box<dyn TimeSource + Send>,
+    default_ttl: Option<Duration>,
+    wheel: Option<TimingWheel>,
 }
 
-impl<
-        K: Default + Hash + Eq + PartialEq + Clone,
-        V: Default + Clone,
-        const L: usize,
-        const S: usize,
-    > InnerCache<K, V, L, S>
-{
+impl<K: Hash + Eq, V, P: Policy> InnerCache<K, V, P> {
     /// Creates a new `InnerCache` instance with default values.
     /// Ensures that the number of sets and log size are powers of two, which is
     /// required for efficient hashing and indexing.
-    fn new() -> Self {
-        assert!(S.is_power_of_two(), "Set size must be a power of two!");
-        assert!(L.is_power_of_two(), "Log size must be a power of two!");
-        let bkt_mask = S - 1;
-        let log_mask = L - 1;
-        Self {
-            sets: [Set::default(); S],
-            log: Log::<K, V, L>::default(),
-            set_mask: bkt_mask,
-            log_mask,
-            log_head: 0,
+    pub(crate) fn new(log_size: usize, sets: usize, mut policy: P) -> Result<Self, BuildError> {
+        if !sets.is_power_of_two() {
+            return Err(BuildError::SetCount(sets));
         }
+        if !log_size.is_power_of_two() {
+            return Err(BuildError::LogSize(log_size));
+        }
+        let bkt_mask = sets - 1;
+        policy.init(sets, log_size);
+        Ok(Self {
+            sets: vec![Set::default(); sets].into_boxed_slice(),
+            log: Log::new(&policy.log_queues(log_size)),
+            set_mask: bkt_mask,
+            len: 0,
+            generation: 0,
+            listener: None,
+            policy: RefCell::new(policy),
+            admission: None,
+            budget: None,
+            weight: 0,
+            time: Box::new(MonotonicTime),
+            default_ttl: None,
+            wheel: None,
+        })
+    }
+
+    /// Returns the number of entries in the log.
+    #[inline]
+    pub(crate) fn log_size(&self) -> usize {
+        self.log.entries.len()
+    }
+
+    /// Returns the number of entries the cache can hold at once.
+    #[inline]
+    fn capacity(&self) -> usize {
+        self.log_size().min(self.sets.len() * SIMD_SIZE)
     }
 
-    /// Probes the cache for a given key and returns the set index, fingerprint,
-    /// and slot index if available.
+    /// Probes the cache for a given key and its hash, and returns the set
+    /// index, fingerprint, and slot index if available.
+    ///
+    /// Every slot whose fingerprint matches is checked against the full key
+    /// stored in the log, so a fingerprint collision never yields another key's
+    /// slot.
     #[inline]
-    fn probe(&self, key: &K) -> (usize, u8, Option<usize>) {
-        let mut hasher = WyHash::with_seed(0);
-        key.hash(&mut hasher);
-        let key_hash = hasher.finish();
+    pub(crate) fn probe<Q>(&self, key_hash: u64, key: &Q) -> (usize, u8, Option<usize>)
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
         let set = self.extract_set(key_hash);
         let finger = self.extract_finger(key_hash);
-        (set, finger, self.sets[set].probe(finger))
+        if self.sets[set].generation != self.generation {
+            return (set, finger, None);
+        }
+        let slot = self.sets[set]
+            .probe(finger)
+            .find(|&slot| self.log.live(self.sets[set].pointers[slot]).key.borrow() == key);
+        (set, finger, slot)
     }
 
-    /// Invalidates an entry in the cache associated with the given key.
-    /// If the key is found, it marks the corresponding slot as invalid.
+    /// Removes the entry associated with the given key and returns its value.
     #[inline]
-    fn invalid(&mut self, key: &K) {
-        match self.probe(key) {
-            (_, _, None) => {}
-            (set, _, Some(slot)) => {
-                self.sets[set].valid_mask &= !(1 << slot);
+    fn remove<Q>(&mut self, key_hash: u64, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let (set, _, slot) = self.probe(key_hash, key);
+        let slot = slot?;
+        if self.expired(set, slot) {
+            self.remove_slot(set, slot, RemovalCause::Expired);
+            return None;
+        }
+        Some(self.remove_slot(set, slot, RemovalCause::Explicit).value)
+    }
+
+    /// Removes the entry held by `slot` of `set` and returns its log item.
+    /// The slot is marked invalid so it can be reused, and the log item is
+    /// moved out so the key and value are released right away instead of when
+    /// the log head comes back around. The listener, if any, is told why.
+    #[inline]
+    pub(crate) fn remove_slot(
+        &mut self,
+        set: usize,
+        slot: usize,
+        cause: RemovalCause,
+    ) -> LogItem<K, V> {
+        self.sets[set].valid_mask &= !(1 << slot);
+        self.len -= 1;
+        let log_pos = self.sets[set].pointers[slot];
+        let item = self
+            .log
+            .take(log_pos)
+            .expect("a valid slot points at an empty log entry");
+        self.weight -= item.weight as u64;
+        self.unschedule(log_pos);
+        if let Some(listener) = self.listener.as_mut() {
+            listener(&item.key, &item.value, cause);
+        }
+        item
+    }
+
+    /// Counts the sets by number of valid slots.
+    fn occupancy(&self) -> Occupancy {
+        let mut sets = [0; SIMD_SIZE + 1];
+        for set in &self.sets {
+            let live = if set.generation == self.generation {
+                set.valid_mask
+            } else {
+                0
+            };
+            sets[live.count_ones() as usize] += 1;
+        }
+        Occupancy {
+            sets,
+            live: self.len,
+            log_entries: self.log_size(),
+            index_slots: self.sets.len() * SIMD_SIZE,
+        }
+    }
+
+    /// Installs the listener fired for every entry that leaves the cache.
+    pub(crate) fn set_listener(&mut self, listener: Option<Listener<K, V>>) {
+        self.listener = listener;
+    }
+
+    /// Installs the admission filter consulted before a new key evicts an
+    /// entry from the log.
+    pub(crate) fn set_admission(&mut self, admission: Option<TinyLfu>) {
+        self.admission = admission.map(RefCell::new);
+    }
+
+    /// Records an access to the key hashing to `hash` in the admission
+    /// filter, if any, whether the key is cached or not.
+    #[inline]
+    pub(crate) fn record(&self, hash: u64) {
+        if let Some(admission) = &self.admission {
+            admission.borrow_mut().record(hash);
+        }
+    }
+
+    /// Installs the weigher and weight budget, re-weighs the live entries, and
+    /// evicts the oldest ones until they fit.
+    pub(crate) fn set_budget(&mut self, budget: Option<Budget<K, V>>) {
+        self.budget = budget;
+        self.weight = 0;
+        for set in 0..self.sets.len() {
+            let mut mask = self.live_mask(set);
+            while mask != 0 {
+                let pos = self.sets[set].pointers[mask.trailing_zeros() as usize];
+                let weight = self.weigh(self.log.live(pos));
+                self.log.live_mut(pos).weight = weight;
+                self.weight += weight as u64;
+                mask &= mask - 1;
+            }
+        }
+        self.shed(0, None, &mut Evictions::new());
+    }
+
+    /// Returns the weight of `item` as measured by the weigher, or 0 without
+    /// a weight budget.
+    #[inline]
+    fn weigh(&self, item: &LogItem<K, V>) -> u32 {
+        self.budget
+            .as_ref()
+            .map_or(0, |budget| budget.weigher.weight(&item.key, &item.value))
+    }
+
+    /// Checks whether an entry of `weight` is heavier than the whole weight
+    /// budget, if any, so no eviction could make room for it.
+    #[inline]
+    fn too_heavy(&self, weight: u32) -> bool {
+        self.budget
+            .as_ref()
+            .is_some_and(|budget| weight as u64 > budget.max_weight)
+    }
+
+    /// Checks whether `value` under `key` weighs no more than the whole
+    /// weight budget, if any.
+    #[inline]
+    pub(crate) fn fits(&self, key: &K, value: &V) -> bool {
+        self.budget
+            .as_ref()
+            .is_none_or(|budget| budget.weigher.weight(key, value) as u64 <= budget.max_weight)
+    }
+
+    /// Weighs the entry in `slot` of `set` again after its value changed in
+    /// place, and evicts the oldest other entries until the weight budget
+    /// holds. An entry that alone weighs more than the whole budget is
+    /// removed instead, and its item returned.
+    pub(crate) fn reweigh(&mut self, set: usize, slot: usize) -> Option<LogItem<K, V>> {
+        self.budget.as_ref()?;
+        let pos = self.sets[set].pointers[slot];
+        let weight = self.weigh(self.log.live(pos));
+        if self.too_heavy(weight) {
+            return Some(self.remove_slot(set, slot, RemovalCause::Weight));
+        }
+        let item = self.log.live_mut(pos);
+        self.weight = self.weight - item.weight as u64 + weight as u64;
+        item.weight = weight;
+        self.shed(0, Some(pos), &mut Evictions::new());
+        None
+    }
+
+    /// Checks whether adding `incoming` to the weight of the live entries
+    /// would exceed the weight budget, if any.
+    #[inline]
+    fn over_budget(&self, incoming: u64) -> bool {
+        self.budget
+            .as_ref()
+            .is_some_and(|budget| self.weight + incoming > budget.max_weight)
+    }
+
+    /// Evicts the oldest live entries, in the order the `ByAge` iterators
+    /// walk them, into `evicted` until `incoming` more weight fits in the
+    /// weight budget. The entry at log position `keep`, if any, is spared.
+    ///
+    /// The queue heads do not move, so the freed log positions are reused
+    /// as the queues come around to them.
+    fn shed(&mut self, incoming: u64, keep: Option<usize>, evicted: &mut Evictions<K, V>) {
+        let mut step = 0;
+        while self.over_budget(incoming) && step < self.log_size() {
+            let pos = self.log.nth_oldest(step);
+            if let Some((set, slot)) = self.back_pointer(pos).filter(|_| Some(pos) != keep) {
+                let old = self.remove_slot(set, slot, RemovalCause::Weight);
+                evicted.push((old.key, old.value));
+            }
+            step += 1;
+        }
+    }
+
+    /// Installs the time source that stamps and expires entries.
+    pub(crate) fn set_time_source(&mut self, time: Box<dyn TimeSource + Send>) {
+        self.time = time;
+    }
+
+    /// Sets the time-to-live of entries inserted without one.
+    pub(crate) fn set_default_ttl(&mut self, ttl: Option<Duration>) {
+        self.default_ttl = ttl;
+    }
+
+    /// Returns the deadline of an entry inserted now with `ttl`, or with the
+    /// default time-to-live if `ttl` is `None`. A time-to-live too long to
+    /// be represented as an `Instant` means the entry never expires.
+    #[inline]
+    pub(crate) fn deadline(&self, ttl: Option<Duration>) -> Option<Instant> {
+        ttl.or(self.default_ttl)
+            .and_then(|ttl| self.time.now().checked_add(ttl))
+    }
+
+    /// Checks whether the entry in `slot` of `set` has outlived its
+    /// time-to-live. The time source is only read for entries that have one.
+    #[inline]
+    pub(crate) fn expired(&self, set: usize, slot: usize) -> bool {
+        self.log
+            .live(self.sets[set].pointers[slot])
+            .deadline
+            .is_some_and(|deadline| self.time.now() >= deadline)
+    }
+
+    /// Removes the entry in `slot` of `set` if it is still there and expired.
+    pub(crate) fn reclaim(&mut self, set: usize, slot: usize) {
+        if self.live_mask(set) & (1 << slot) != 0 && self.expired(set, slot) {
+            self.remove_slot(set, slot, RemovalCause::Expired);
+        }
+    }
+
+    /// Installs a timing wheel with ticks of `resolution`, or removes it,
+    /// and schedules the deadlines of the live entries in it.
+    pub(crate) fn set_wheel(&mut self, resolution: Option<Duration>) {
+        self.wheel = resolution
+            .map(|resolution| TimingWheel::new(self.time.now(), resolution, self.log_size()));
+        for set in 0..self.sets.len() {
+            let mut mask = self.live_mask(set);
+            while mask != 0 {
+                self.schedule(self.sets[set].pointers[mask.trailing_zeros() as usize]);
+                mask &= mask - 1;
+            }
+        }
+    }
+
+    /// Schedules the deadline of the live item at log position `pos` in the
+    /// timing wheel, if there is one, replacing the timer of the position.
+    #[inline]
+    fn schedule(&mut self, pos: usize) {
+        if let Some(wheel) = self.wheel.as_mut() {
+            match self.log.live(pos).deadline {
+                Some(deadline) => wheel.schedule(pos, deadline),
+                None => wheel.cancel(pos),
+            }
+        }
+    }
+
+    /// Drops the timer of log position `pos` from the timing wheel, if any.
+    #[inline]
+    fn unschedule(&mut self, pos: usize) {
+        if let Some(wheel) = self.wheel.as_mut() {
+            wheel.cancel(pos);
+        }
+    }
+
+    /// Advances the timing wheel to `now` and removes every entry whose
+    /// deadline has passed. Timers of entries dropped by `flush_all` are
+    /// skipped.
+    ///
+    /// Returns the number of entries removed.
+    fn tick(&mut self, now: Instant) -> usize {
+        let mut fired = Vec::new();
+        match self.wheel.as_mut() {
+            Some(wheel) => wheel.advance(now, &mut fired),
+            None => return 0,
+        }
+        let mut expired = 0;
+        for timer in fired {
+            let Some((set, slot)) = self.back_pointer(timer.pos) else {
+                continue;
+            };
+            if self.log.live(timer.pos).deadline == Some(timer.deadline) {
+                self.remove_slot(set, slot, RemovalCause::Expired);
+                expired += 1;
+            }
+        }
+        expired
+    }
+
+    /// Removes every expired entry and returns how many there were.
+    fn purge_expired(&mut self) -> usize {
+        let mut purged = 0;
+        for set in 0..self.sets.len() {
+            let mut mask = self.live_mask(set);
+            while mask != 0 {
+                let slot = mask.trailing_zeros() as usize;
+                if self.expired(set, slot) {
+                    self.remove_slot(set, slot, RemovalCause::Expired);
+                    purged += 1;
+                }
+                mask &= mask - 1;
+            }
+        }
+        purged
+    }
+
+    /// Reports to the policy that a lookup found `slot` of `set`.
+    #[inline]
+    pub(crate) fn hit(&self, set: usize, slot: usize) {
+        let pos = self.sets[set].pointers[slot];
+        self.policy.borrow_mut().on_hit(set, slot, pos);
+    }
+
+    /// Returns the log position that `slot` of `set` points at.
+    #[inline]
+    pub(crate) fn position(&self, set: usize, slot: usize) -> usize {
+        self.sets[set].pointers[slot]
+    }
+
+    /// Returns the valid slots of `set`, or none if the set predates the last
+    /// `flush_all`.
+    #[inline]
+    pub(crate) fn live_mask(&self, set: usize) -> u16 {
+        if self.sets[set].generation == self.generation {
+            self.sets[set].valid_mask
+        } else {
+            0
+        }
+    }
+
+    /// Checks whether `slot` of `set` is valid and points at log position `pos`.
+    #[inline]
+    fn references(&self, set: usize, slot: usize, pos: usize) -> bool {
+        self.live_mask(set) & (1 << slot) != 0 && self.sets[set].pointers[slot] == pos
+    }
+
+    /// Invalidates every entry in O(1) by moving to a new generation.
+    ///
+    /// Sets from older generations read as empty and are reset on their next
+    /// insert, and the stale log items are dropped as the log head reaches
+    /// them, firing the listener with `RemovalCause::Flushed`. Generations are
+    /// 32 bits wide, so a set would have to sit untouched through 2^32 flushes
+    /// to be misread.
+    fn flush_all(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        self.len = 0;
+        self.weight = 0;
+    }
+
+    /// Removes every entry right away, firing the listener for each live one,
+    /// and resets the log so no stale item keeps holding memory.
+    fn clear(&mut self) {
+        for set in 0..self.sets.len() {
+            let mut mask = self.live_mask(set);
+            while mask != 0 {
+                let slot = mask.trailing_zeros() as usize;
+                self.remove_slot(set, slot, RemovalCause::Explicit);
+                mask &= mask - 1;
+            }
+        }
+        for pos in 0..self.log_size() {
+            self.drop_flushed(pos);
+        }
+        self.log.clear();
+        if let Some(wheel) = self.wheel.as_mut() {
+            wheel.clear();
+        }
+    }
+
+    /// Returns the set and slot of the log item at `pos` if a valid slot still
+    /// points at it, meaning the item is live.
+    #[inline]
+    pub(crate) fn back_pointer(&self, pos: usize) -> Option<(usize, usize)> {
+        let entry = self.log.entries[pos].as_ref()?;
+        let (set, slot) = (self.extract_set(entry.hash), entry.slot);
+        self.references(set, slot, pos).then_some((set, slot))
+    }
+
+    /// Drops the item at log position `pos`, if any, that no slot points at.
+    /// Removed and moved items leave their position empty, so such an item
+    /// was invalidated by `flush_all`; the listener is fired for it with
+    /// `RemovalCause::Flushed`.
+    fn drop_flushed(&mut self, pos: usize) {
+        if let Some(item) = self.log.take(pos) {
+            self.unschedule(pos);
+            if let Some(listener) = self.listener.as_mut() {
+                listener(&item.key, &item.value, RemovalCause::Flushed);
+            }
+        }
+    }
+
+    /// Frees the head of log queue `queue` so it can be overwritten, and
+    /// returns its position.
+    ///
+    /// An item at the head that no slot points at was flushed, and is dropped
+    /// with `drop_flushed`. A live one is handed to the policy: it is either
+    /// evicted into `evicted`, or requeued. Requeueing into the same queue
+    /// skips over it, and into another queue moves it to the head of that
+    /// queue, which is freed first the same way. Only the slot named by an
+    /// item's back-pointer is ever cleared or repointed, so slots reused by
+    /// other keys are never touched.
+    fn make_room(&mut self, queue: usize, evicted: &mut Evictions<K, V>) -> usize {
+        loop {
+            let pos = self.log.head(queue);
+            let Some((set, slot)) = self.back_pointer(pos) else {
+                self.drop_flushed(pos);
+                return pos;
+            };
+            let hash = self.log.live(pos).hash;
+            match self.policy.get_mut().reclaim(queue, pos, hash) {
+                Reclaim::Evict => {
+                    let old = self.remove_slot(set, slot, RemovalCause::LogWrap);
+                    evicted.push((old.key, old.value));
+                    return pos;
+                }
+                Reclaim::Requeue(to_queue) if to_queue == queue => self.log.advance(queue),
+                Reclaim::Requeue(to_queue) => {
+                    let to = self.make_room(to_queue, evicted);
+                    self.log.entries[to] = self.log.take(pos);
+                    self.sets[set].pointers[slot] = to;
+                    self.log.advance(to_queue);
+                    self.policy.get_mut().on_move(pos, to);
+                    self.schedule(to);
+                    self.unschedule(pos);
+                    return pos;
+                }
             }
         }
     }
 
     /// Inserts a log item into the cache, replacing the oldest entry if necessary.
     /// If the key already exists, it updates the entry; otherwise, it inserts
-    /// the new item and adjusts the log head.
-    fn insert(&mut self, item: LogItem<K, V>) {
-        let (set, finger, way) = self.probe(&item.key);
+    /// the new item and adjusts the log head. The item must already carry its
+    /// hash. It expires after `ttl`, or after the default time-to-live if
+    /// `ttl` is `None`.
+    ///
+    /// With an admission filter, a new key whose log queue head is a live
+    /// entry is only inserted if it is more frequent than that entry, even if
+    /// the policy ends up evicting another one. With a
+    /// weight budget, an item heavier than the whole budget is rejected and
+    /// any cached entry for its key removed, and an update that makes its
+    /// entry heavier evicts the oldest other entries until the budget holds
+    /// again.
+    fn insert(&mut self, mut item: LogItem<K, V>, ttl: Option<Duration>) -> Insertion<K, V> {
+        self.record(item.hash);
+        item.weight = self.weigh(&item);
+        item.deadline = self.deadline(ttl);
+        let (set, finger, mut way) = self.probe(item.hash, &item.key);
+        if let Some(slot) = way.filter(|&slot| self.expired(set, slot)) {
+            self.remove_slot(set, slot, RemovalCause::Expired);
+            way = None;
+        }
+        if self.too_heavy(item.weight) {
+            if let Some(slot) = way {
+                self.remove_slot(set, slot, RemovalCause::Weight);
+            }
+            return Insertion::Rejected(item.key, item.value);
+        }
 
         match way {
             None => {
-                let mut log_head = self.log_head;
-                let old_key = self.log.entries[log_head].key.clone();
-                self.invalid(&old_key);
-                let slot = self.sets[set].next_slot();
-
-                self.sets[set].set_finger(slot, finger);
-                self.sets[set].valid_mask |= 1 << slot;
-                self.sets[set].pointers[slot] = log_head;
-
-                self.log.entries[log_head & self.log_mask] = item;
-                log_head = (log_head + 1) % L;
-                self.log_head = log_head;
+                let queue = self.policy.get_mut().queue_for(item.hash);
+                if let Some(admission) = &self.admission {
+                    let head = self.log.head(queue);
+                    if self.back_pointer(head).is_some()
+                        && !admission
+                            .borrow()
+                            .admit(item.hash, self.log.live(head).hash)
+                    {
+                        return Insertion::Rejected(item.key, item.value);
+                    }
+                }
+                let mut evicted = Evictions::new();
+                self.insert_into(queue, set, finger, item, &mut evicted);
+                if evicted.is_empty() {
+                    Insertion::Inserted
+                } else {
+                    Insertion::Evicted(evicted)
+                }
             }
             Some(slot) => {
+                self.hit(set, slot);
                 let pointer = self.sets[set].pointers[slot];
-                self.log.entries[pointer] = item;
+                item.slot = slot;
+                self.weight += item.weight as u64;
+                let old = std::mem::replace(self.log.live_mut(pointer), item);
+                self.schedule(pointer);
+                self.weight -= old.weight as u64;
+                let mut evicted = Evictions::new();
+                self.shed(0, Some(pointer), &mut evicted);
+                Insertion::Updated {
+                    old: old.value,
+                    evicted,
+                }
             }
         }
     }
 
-    /// Retrieves a value from the cache for a given key.
-    /// Returns `Some(value)` if the key exists and is valid, `None` otherwise.
-    fn get(&self, key: &K) -> Option<V> {
-        let (set, _, slot) = self.probe(key);
-        match slot {
-            Some(slot) => {
-                if (self.sets[set].valid_mask & (1 << slot)) == 0 {
-                    return None;
-                }
+    /// Inserts a log item for a key that `probe` did not find, given the set
+    /// and fingerprint it returned. The item must already carry its hash.
+    ///
+    /// The policy picks the log queue the item goes to. The live entry the
+    /// policy evicts from that queue and, if the set is full, the entry in
+    /// the slot picked by the policy are moved into `evicted`.
+    /// Returns the log position the item was written to, or the item itself,
+    /// untouched and with nothing evicted, if it weighs more than the whole
+    /// weight budget.
+    pub(crate) fn insert_new(
+        &mut self,
+        set: usize,
+        finger: u8,
+        mut item: LogItem<K, V>,
+        evicted: &mut Evictions<K, V>,
+    ) -> Result<usize, LogItem<K, V>> {
+        item.weight = self.weigh(&item);
+        if self.too_heavy(item.weight) {
+            return Err(item);
+        }
+        item.deadline = self.deadline(None);
+        let queue = self.policy.get_mut().queue_for(item.hash);
+        Ok(self.insert_into(queue, set, finger, item, evicted))
+    }
 
-                let log_pos = self.sets[set].pointers[slot];
-                Some(self.log.entries[log_pos].value.clone())
+    /// Same as `insert_new`, for an item whose log queue is already chosen
+    /// and whose weight is already measured.
+    fn insert_into(
+        &mut self,
+        queue: usize,
+        set: usize,
+        finger: u8,
+        mut item: LogItem<K, V>,
+        evicted: &mut Evictions<K, V>,
+    ) -> usize {
+        self.shed(item.weight as u64, None, evicted);
+        let pos = self.make_room(queue, evicted);
+        if self.sets[set].generation != self.generation {
+            self.sets[set].reset(self.generation);
+        }
+        let slot = match self.sets[set].free_slot() {
+            Some(slot) => slot,
+            None => {
+                let slot = self.policy.get_mut().choose_victim(set);
+                let old = self.remove_slot(set, slot, RemovalCause::SetFull);
+                evicted.push((old.key, old.value));
+                slot
             }
-            None => None,
+        };
+
+        self.sets[set].set_finger(slot, finger);
+        self.sets[set].valid_mask |= 1 << slot;
+        self.sets[set].pointers[slot] = pos;
+        self.len += 1;
+        self.weight += item.weight as u64;
+        self.policy.get_mut().on_insert(set, slot, pos);
+
+        item.slot = slot;
+        self.log.entries[pos] = Some(item);
+        self.schedule(pos);
+        self.log.advance(queue);
+        pos
+    }
+
+    /// Retrieves a value from the cache for a given key.
+    /// Returns a hit with the value if the key exists and is valid. An expired
+    /// entry reads as a miss, but its place is returned so the caller can
+    /// reclaim it once it can borrow the cache mutably.
+    fn get<Q>(&self, key_hash: u64, key: &Q) -> Lookup<'_, V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.record(key_hash);
+        let (set, _, slot) = self.probe(key_hash, key);
+        let Some(slot) = slot else {
+            return Lookup::Miss;
+        };
+        if self.expired(set, slot) {
+            return Lookup::Expired(set, slot);
+        }
+        self.hit(set, slot);
+        Lookup::Hit(&self.log.live(self.sets[set].pointers[slot]).value)
+    }
+
+    /// Prefetches the set that a key hash maps to.
+    #[inline]
+    fn prefetch_set(&self, key_hash: u64) {
+        prefetch(&self.sets[self.extract_set(key_hash)]);
+    }
+
+    /// Prefetches the log items of every slot in the key's set whose
+    /// fingerprint matches, which `probe` reads to compare the full key.
+    /// The set itself should be prefetched first.
+    #[inline]
+    fn prefetch_log(&self, key_hash: u64) {
+        let set = &self.sets[self.extract_set(key_hash)];
+        for slot in set.probe(self.extract_finger(key_hash)) {
+            prefetch(&self.log.entries[set.pointers[slot]]);
         }
     }
 
-    /// Extracts the set index from the hash key using the set mask.
+    /// Prefetches the sets of a batch of hashes, then the log items they
+    /// point at, so resolving the batch afterwards mostly hits in the cache.
+    fn prefetch_batch(&self, hashes: impl Iterator<Item = u64> + Clone) {
+        hashes.clone().for_each(|hash| self.prefetch_set(hash));
+        hashes.for_each(|hash| self.prefetch_log(hash));
+    }
+
+    /// Extracts the set index from the hash key.
+    ///
+    /// The hash is multiplied by an odd constant first and the index is taken
+    /// from the upper half of the product, which depends on every lower bit of
+    /// the hash. Keys a caller routes on the low bits of their hash, such as
+    /// `hash % shards`, thus still spread over all sets.
     #[inline]
     fn extract_set(&self, key: u64) -> usize {
-        (key as usize) & self.set_mask
+        ((key.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 32) as usize) & self.set_mask
     }
 
     /// Extracts the fingerprint from the hash key.
+    ///
+    /// The fingerprint comes from the top byte, which the set index never uses
+    /// for any realistic set count, so keys sharing a set still spread across
+    /// fingerprints. `EMPTY_FINGER` is reserved and folded into the next value.
     #[inline]
-    fn extract_finger(&self, key: u64) -> u8 {
-        (key & 0xFF) as u8
+    pub(crate) fn extract_finger(&self, key: u64) -> u8 {
+        ((key >> 56) as u8).max(EMPTY_FINGER + 1)
     }
 }
 
@@ -145,21 +780,55 @@ impl<
 /// It provides methods to insert, retrieve, and invalidate entries.
 /// Internally, it manages an `InnerCache` instance wrapped in a RefCell for interior mutability.
 ///
+/// Lookups accept any borrowed form of the key, like `HashMap`, so a table
+/// keyed by `String` can be queried with a `&str`.
+///
 /// # Type Parameters
-/// - `K`: Key type, must implement `Default`, `Hash`, `Eq`, `PartialEq`, and `Clone`.
-/// - `V`: Value type, must implement `Default` and `Clone`.
+/// - `K`: Key type, must implement `Hash` and `Eq`.
+/// - `V`: Value type. `get` additionally needs `Clone`.
 /// - `L`: Log size, must be a power of two.
 /// - `B`: Number of sets in the cache, must be a power of two.
-pub struct CacheTable<K, V, const L: usize, const B: usize> {
-    inner: RefCell<InnerCache<K, V, L, B>>,
+/// - `S`: The `BuildHasher` that places keys in sets, `WyHashBuilder` by default.
+///   A keyed hasher protects against untrusted keys, and an identity hasher
+///   suits keys that already are hashes.
+/// - `P`: The eviction `Policy` of full sets, `Fifo` by default.
+///
+/// Both the log and the sets live on the heap. `L` and `B` only pick their
+/// sizes; see `DynCacheTable` for a table sized at runtime. Sizes that are not
+/// powers of two are refused when the table's constructor is compiled:
+///
+/// ```compile_fail
+/// let ctable = cachetable::CacheTable::<u32, u32, 3, 32>::new();
+/// ```
+pub struct CacheTable<K, V, const L: usize, const B: usize, S = WyHashBuilder, P = Fifo> {
+    inner: RefCell<InnerCache<K, V, P>>,
+    hasher: S,
 }
 
+/// The `L` and `B` of a `CacheTable` whose sizes are chosen at runtime.
+pub const DYNAMIC: usize = 0;
+
+/// A `CacheTable` whose log size and number of sets are chosen at runtime
+/// through a `CacheTableBuilder`, rather than as const parameters.
+///
+/// It offers the same methods as any other `CacheTable`, but `DYNAMIC` is no
+/// size, so `new`, `default` and the other constructors fail to compile for
+/// it and the builder is the only way to make one:
+///
+/// ```compile_fail
+/// let ctable = cachetable::DynCacheTable::<u32, u32>::new();
+/// ```
+pub type DynCacheTable<K, V, S = WyHashBuilder, P = Fifo> =
+    CacheTable<K, V, DYNAMIC, DYNAMIC, S, P>;
+
 impl<
-        K: Default + Hash + Eq + PartialEq + Clone,
-        V: Default + Clone,
+        K: Hash + Eq,
+        V,
         const L: usize,
         const B: usize,
-    > CacheTable<K, V, L, B>
+        S: BuildHasher + Default,
+        P: Policy + Default,
+    > CacheTable<K, V, L, B, S, P>
 {
     /// Creates a new `CacheTable` instance.
     ///
@@ -168,61 +837,672 @@ impl<
     pub fn new() -> Self {
         Self::default()
     }
+}
+
+impl<K: Hash + Eq, V, const L: usize, const B: usize, P: Policy + Default>
+    CacheTable<K, V, L, B, WyHashBuilder, P>
+{
+    /// Creates a new `CacheTable` instance that hashes keys from a fixed seed
+    /// instead of a random one, so keys land in the same sets on every run.
+    ///
+    /// # Arguments
+    /// * `seed` - The seed of the WyHash hasher.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_hasher(WyHashBuilder::with_seed(seed))
+    }
+}
+
+impl<K: Hash + Eq, V, const L: usize, const B: usize, S: BuildHasher, P: Policy + Default>
+    CacheTable<K, V, L, B, S, P>
+{
+    /// Creates a new `CacheTable` instance that hashes keys with `hasher`.
+    ///
+    /// # Arguments
+    /// * `hasher` - The `BuildHasher` used to place keys in sets.
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::with_hasher_and_policy(hasher, P::default())
+    }
+}
+
+impl<K: Hash + Eq, V, const L: usize, const B: usize, S: BuildHasher, P: Policy>
+    CacheTable<K, V, L, B, S, P>
+{
+    /// Creates a new `CacheTable` instance that hashes keys with `hasher` and
+    /// evicts from full sets with `policy`.
+    ///
+    /// # Arguments
+    /// * `hasher` - The `BuildHasher` used to place keys in sets.
+    /// * `policy` - The `Policy` that picks the victim of a full set.
+    pub fn with_hasher_and_policy(hasher: S, policy: P) -> Self {
+        const {
+            assert!(
+                L.is_power_of_two() && B.is_power_of_two(),
+                "L and B must be powers of two"
+            )
+        };
+        let inner = InnerCache::new(L, B, policy).expect("L and B are powers of two");
+        Self::from_inner(inner, hasher)
+    }
+
+    /// Wraps an already sized `InnerCache`.
+    pub(crate) fn from_inner(inner: InnerCache<K, V, P>, hasher: S) -> Self {
+        Self {
+            inner: RefCell::new(inner),
+            hasher,
+        }
+    }
+
+    /// Returns the `BuildHasher` used to place keys in sets.
+    pub fn hasher(&self) -> &S {
+        &self.hasher
+    }
+
+    /// Inserts a key-value pair into the cache.
+    ///
+    /// If the key already exists, its value will be updated.
+    ///
+    /// # Arguments
+    /// * `key` - The key to insert.
+    /// * `value` - The value associated with the key.
+    ///
+    /// # Returns
+    /// An `Insertion` telling whether the key was new, updated or rejected by
+    /// the admission filter, and handing back the previous value, any evicted
+    /// entries or the rejected pair.
+    pub fn insert(&self, key: K, value: V) -> Insertion<K, V> {
+        self.insert_hashed(self.hash_key(&key), key, value)
+    }
+
+    /// Same as `insert`, for an entry that expires once `ttl` has passed,
+    /// whatever the default time-to-live.
+    ///
+    /// An expired entry reads as a miss, and its slot is reclaimed by the
+    /// lookup that finds it, by `purge_expired`, or when the log wraps. A
+    /// `ttl` too long to add to the current time, such as `Duration::MAX`,
+    /// never expires.
+    ///
+    /// # Arguments
+    /// * `key` - The key to insert.
+    /// * `value` - The value associated with the key.
+    /// * `ttl` - How long the entry lives, as measured by the time source.
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Insertion<K, V> {
+        let hash = self.hash_key(&key);
+        let item = LogItem::new(key, value, hash);
+        let mut inner = self.inner.borrow_mut();
+        inner.insert(item, Some(ttl))
+    }
+
+    /// Hashes a key the way the table does to place it in a set.
+    ///
+    /// The result can be used to route the key, for instance to pick a shard,
+    /// and then passed to the `_hashed` methods so the key is hashed only once.
+    /// Route on the low 32 bits, as `hash % shards` does: the set index mixes
+    /// them with the rest of the hash, while the top byte is the fingerprint,
+    /// which keys routed on it would all share.
+    ///
+    /// # Arguments
+    /// * `key` - The key to hash.
+    pub fn hash_key<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    /// Same as `insert`, for a key whose hash the caller already computed.
+    ///
+    /// The hash decides the set and fingerprint of the key, so every operation
+    /// on a key must be given the same hash: use `hash_key`, or pass the
+    /// caller's own hash to the `_hashed` methods consistently.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of `key`.
+    /// * `key` - The key to insert.
+    /// * `value` - The value associated with the key.
+    pub fn insert_hashed(&self, hash: u64, key: K, value: V) -> Insertion<K, V> {
+        let item = LogItem::new(key, value, hash);
+        let mut inner = self.inner.borrow_mut();
+        inner.insert(item, None)
+    }
+
+    /// Inserts a batch of key-value pairs into the cache, in order.
+    ///
+    /// All keys are hashed first, then their sets and any log items with a
+    /// matching fingerprint are prefetched, and only then are the pairs
+    /// inserted, so the memory accesses of the batch overlap.
+    ///
+    /// # Arguments
+    /// * `items` - The key-value pairs to insert.
+    ///
+    /// # Returns
+    /// The `Insertion` of every pair, in order.
+    pub fn insert_many<I>(&self, items: I) -> Vec<Insertion<K, V>>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let items: Vec<_> = items
+            .into_iter()
+            .map(|(key, value)| {
+                let hash = self.hasher.hash_one(&key);
+                LogItem::new(key, value, hash)
+            })
+            .collect();
+        let mut inner = self.inner.borrow_mut();
+        inner.prefetch_batch(items.iter().map(|item| item.hash));
+        items
+            .into_iter()
+            .map(|item| inner.insert(item, None))
+            .collect()
+    }
+
+    /// Retrieves the value associated with the given key from the cache.
+    ///
+    /// The value is cloned out of the log; use `get_with` to read it in place.
+    ///
+    /// # Arguments
+    /// * `key` - The key to retrieve.
+    ///
+    /// # Returns
+    /// An `Option` containing the value if the key exists and is valid, `None` otherwise.
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        V: Clone,
+    {
+        self.get_hashed(self.hash_key(key), key)
+    }
+
+    /// Same as `get`, for a key whose hash the caller already computed.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of `key`, as given to `insert_hashed` or returned by `hash_key`.
+    /// * `key` - The key to retrieve.
+    pub fn get_hashed<Q>(&self, hash: u64, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+        V: Clone,
+    {
+        let inner = self.inner.borrow();
+        match inner.get(hash, key) {
+            Lookup::Hit(value) => Some(value.clone()),
+            Lookup::Expired(set, slot) => {
+                drop(inner);
+                self.reclaim(&[(set, slot)]);
+                None
+            }
+            Lookup::Miss => None,
+        }
+    }
+
+    /// Retrieves the values associated with a batch of keys.
+    ///
+    /// All keys are hashed first, then their sets and any log items with a
+    /// matching fingerprint are prefetched, and only then are the lookups
+    /// resolved, so the cache misses of the batch overlap instead of being
+    /// paid one after the other.
+    ///
+    /// # Arguments
+    /// * `keys` - The keys to retrieve.
+    ///
+    /// # Returns
+    /// The value of every key, in order, or `None` for the keys not cached.
+    pub fn get_many<Q>(&self, keys: &[Q]) -> Vec<Option<V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq,
+        V: Clone,
+    {
+        let hashes: Vec<u64> = keys.iter().map(|key| self.hasher.hash_one(key)).collect();
+        let inner = self.inner.borrow();
+        inner.prefetch_batch(hashes.iter().copied());
+        let mut expired = Vec::new();
+        let values = keys
+            .iter()
+            .zip(hashes)
+            .map(|(key, hash)| match inner.get(hash, key) {
+                Lookup::Hit(value) => Some(value.clone()),
+                Lookup::Expired(set, slot) => {
+                    expired.push((set, slot));
+                    None
+                }
+                Lookup::Miss => None,
+            })
+            .collect();
+        drop(inner);
+        self.reclaim(&expired);
+        values
+    }
+
+    /// Calls `f` with a reference to the value associated with the given key,
+    /// without cloning it.
+    ///
+    /// The table stays borrowed while `f` runs, so `f` must not insert into or
+    /// remove from the same table.
+    ///
+    /// # Arguments
+    /// * `key` - The key to retrieve.
+    /// * `f` - The closure applied to the cached value.
+    ///
+    /// # Returns
+    /// An `Option` containing the result of `f` if the key exists and is valid, `None` otherwise.
+    pub fn get_with<Q, R, F>(&self, key: &Q, f: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&V) -> R,
+    {
+        let hash = self.hasher.hash_one(key);
+        let inner = self.inner.borrow();
+        match inner.get(hash, key) {
+            Lookup::Hit(value) => Some(f(value)),
+            Lookup::Expired(set, slot) => {
+                drop(inner);
+                self.reclaim(&[(set, slot)]);
+                None
+            }
+            Lookup::Miss => None,
+        }
+    }
+
+    /// Checks whether the given key is cached, without touching its value.
+    ///
+    /// # Arguments
+    /// * `key` - The key to look up.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.hasher.hash_one(key);
+        let inner = self.inner.borrow();
+        match inner.probe(hash, key) {
+            (set, _, Some(slot)) if inner.expired(set, slot) => {
+                drop(inner);
+                self.reclaim(&[(set, slot)]);
+                false
+            }
+            (_, _, slot) => slot.is_some(),
+        }
+    }
+
+    /// Removes the entries that lookups found expired.
+    ///
+    /// If the table is still borrowed, by an iterator for instance, they are
+    /// left for a later lookup, `purge_expired` or the log head to reclaim.
+    fn reclaim(&self, expired: &[(usize, usize)]) {
+        if let Ok(mut inner) = self.inner.try_borrow_mut() {
+            for &(set, slot) in expired {
+                inner.reclaim(set, slot);
+            }
+        }
+    }
+
+    /// Gets the entry for the given key, for in-place lookup and insertion.
+    ///
+    /// The key is hashed and probed once; filling a vacant entry reuses that
+    /// result instead of probing again. The table stays borrowed until the
+    /// entry, or any reference obtained from it, is dropped. An expired entry
+    /// is removed and the key reads as vacant.
+    ///
+    /// # Arguments
+    /// * `key` - The key to look up.
+    pub fn entry(&self, key: K) -> Entry<'_, K, V, P> {
+        let hash = self.hasher.hash_one(&key);
+        let mut inner = self.inner.borrow_mut();
+        inner.record(hash);
+        match inner.probe(hash, &key) {
+            (set, finger, Some(slot)) if inner.expired(set, slot) => {
+                inner.remove_slot(set, slot, RemovalCause::Expired);
+                Entry::Vacant(VacantEntry::new(inner, key, hash, set, finger))
+            }
+            (set, _, Some(slot)) => {
+                inner.hit(set, slot);
+                Entry::Occupied(OccupiedEntry::new(inner, set, slot))
+            }
+            (set, finger, None) => Entry::Vacant(VacantEntry::new(inner, key, hash, set, finger)),
+        }
+    }
+
+    /// Returns the number of live entries in the cache.
+    ///
+    /// Expired entries are counted until they are reclaimed.
+    pub fn len(&self) -> usize {
+        self.inner.borrow().len
+    }
+
+    /// Returns `true` if the cache holds no live entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of entries the cache can hold at once: the smaller
+    /// of the log size `L` and the `B * 16` slots across all sets.
+    pub fn capacity(&self) -> usize {
+        self.inner.borrow().capacity()
+    }
+
+    /// Reports how full the sets and the log are.
+    ///
+    /// This walks every set, so it costs time proportional to `B`.
+    pub fn occupancy(&self) -> Occupancy {
+        self.inner.borrow().occupancy()
+    }
+
+    /// Returns an iterator over the live entries, set by set.
+    ///
+    /// Entries that were evicted, removed or never written are skipped. The
+    /// table stays borrowed while the iterator or any item it yielded lives.
+    pub fn iter(&self) -> Iter<'_, K, V, P> {
+        self.iter_in(IterOrder::BySet)
+    }
+
+    /// Returns an iterator over the live entries in the given order.
+    ///
+    /// # Arguments
+    /// * `order` - Whether to walk the sets or the log, oldest entry first.
+    pub fn iter_in(&self, order: IterOrder) -> Iter<'_, K, V, P> {
+        Iter::new(self.inner.borrow(), order)
+    }
+
+    /// Returns an iterator over the keys of the live entries, set by set.
+    pub fn keys(&self) -> Keys<'_, K, V, P> {
+        Keys(self.iter())
+    }
+
+    /// Returns an iterator over the values of the live entries, set by set.
+    pub fn values(&self) -> Values<'_, K, V, P> {
+        Values(self.iter())
+    }
+
+    /// Removes every live entry and returns them by value, oldest first, so
+    /// they can be re-inserted elsewhere in their original order.
+    pub fn drain(&self) -> Drain<'_, K, V, P> {
+        Drain::new(self.inner.borrow_mut())
+    }
+
+    /// Removes every entry from the cache right away.
+    ///
+    /// The listener fires for each live entry, and every log item is reset so
+    /// their memory is released immediately. This costs time proportional to
+    /// `L + B`; use `flush_all` to invalidate everything in constant time.
+    pub fn clear(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.clear();
+    }
+
+    /// Invalidates every entry in constant time, like memcached's `flush_all`.
+    ///
+    /// A generation counter is bumped so all current entries read as misses
+    /// without touching the sets or the log. Their memory is reclaimed lazily
+    /// as the log head advances over them, or by `clear`, and the listener is
+    /// fired for each then with `RemovalCause::Flushed`.
+    pub fn flush_all(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.flush_all();
+    }
+
+    /// Registers a listener fired with the key, value and cause of every
+    /// entry that leaves the cache, replacing any previous one.
+    ///
+    /// The listener runs while the table is borrowed, so it must not call
+    /// back into the same table.
+    ///
+    /// # Arguments
+    /// * `listener` - The callback to fire on every removal.
+    pub fn set_listener<F>(&self, listener: F)
+    where
+        F: FnMut(&K, &V, RemovalCause) + Send + 'static,
+    {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_listener(Some(Box::new(listener)));
+    }
+
+    /// Unregisters the removal listener, if any.
+    pub fn clear_listener(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_listener(None);
+    }
+
+    /// Installs a TinyLFU admission filter, replacing any previous one.
+    ///
+    /// Every lookup, hit or miss, and every insert counts as an access to
+    /// the key. Once the log is full, `insert` compares the new key's
+    /// frequency with that of the live entry it would evict from the log and
+    /// returns `Insertion::Rejected` unless the new key is more frequent, so
+    /// a scan of cold keys does not flush the working set. Updates of cached
+    /// keys and vacant entries filled through `entry` are always admitted.
+    ///
+    /// The new key is only compared with the live entry at the head of the
+    /// log queue it goes to, which is not always the entry that leaves. A
+    /// policy that requeues that entry, like `S3Fifo`, evicts another one
+    /// instead, and when the key's set is full the policy's victim in the set
+    /// is evicted as well; neither of those is weighed against the new key.
+    ///
+    /// # Arguments
+    /// * `admission` - The filter, usually sized with `TinyLfu::new(capacity)`.
+    pub fn set_admission(&self, admission: TinyLfu) {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_admission(Some(admission));
+    }
+
+    /// Removes the admission filter, if any, so every insert is admitted.
+    pub fn clear_admission(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_admission(None);
+    }
+
+    /// Bounds the total weight of the live entries, replacing any previous
+    /// weigher and budget.
+    ///
+    /// Entries are weighed when inserted or updated; changing a value in
+    /// place through `entry` does not weigh it again. A new entry first
+    /// evicts the oldest entries, with `RemovalCause::Weight`, until it fits,
+    /// and `insert` returns `Insertion::Rejected` for entries heavier than
+    /// `max_weight`. Entries already cached are weighed right away, and the
+    /// oldest ones are evicted if they do not fit.
+    ///
+    /// # Arguments
+    /// * `weigher` - Measures the weight of each entry, e.g. in bytes.
+    /// * `max_weight` - The total weight the live entries may add up to.
+    pub fn set_weigher<W>(&self, weigher: W, max_weight: u64)
+    where
+        W: Weigher<K, V> + Send + 'static,
+    {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_budget(Some(Budget {
+            weigher: Box::new(weigher),
+            max_weight,
+        }));
+    }
+
+    /// Removes the weigher and weight budget, if any, so capacity is counted
+    /// in entries only.
+    pub fn clear_weigher(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_budget(None);
+    }
+
+    /// Returns the total weight of the live entries, as measured by the
+    /// weigher, or 0 without one.
+    pub fn weight(&self) -> u64 {
+        self.inner.borrow().weight
+    }
+
+    /// Gives entries inserted without a time-to-live this one, replacing any
+    /// previous default. Entries already cached keep their own deadline.
+    ///
+    /// # Arguments
+    /// * `ttl` - How long new entries live, as measured by the time source.
+    pub fn set_default_ttl(&self, ttl: Duration) {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_default_ttl(Some(ttl));
+    }
+
+    /// Removes the default time-to-live, so entries inserted without one
+    /// never expire.
+    pub fn clear_default_ttl(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_default_ttl(None);
+    }
+
+    /// Replaces the time source that stamps entries with their deadline and
+    /// tells whether they expired. The system's monotonic clock is used by
+    /// default; `ManualTime` makes expiration deterministic in tests.
+    ///
+    /// Deadlines already stamped are kept, so the time source should be set
+    /// before inserting entries with a time-to-live.
+    ///
+    /// # Arguments
+    /// * `time` - The new time source.
+    pub fn set_time_source<T>(&self, time: T)
+    where
+        T: TimeSource + Send + 'static,
+    {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_time_source(Box::new(time));
+    }
+
+    /// Removes every expired entry, firing the listener with
+    /// `RemovalCause::Expired` for each.
+    ///
+    /// This walks every set, so it costs time proportional to `B`.
+    ///
+    /// # Returns
+    /// The number of entries removed.
+    pub fn purge_expired(&self) -> usize {
+        let mut inner = self.inner.borrow_mut();
+        inner.purge_expired()
+    }
+
+    /// Attaches a hierarchical timing wheel that tracks the deadlines of the
+    /// entries with a time-to-live, replacing any previous one.
+    ///
+    /// With a wheel, `tick` reclaims expired entries in time proportional to
+    /// the number of expirations rather than to the size of the table. The
+    /// wheel holds at most one timer per log position, however often entries
+    /// are overwritten. Deadlines are rounded up to whole ticks of
+    /// `resolution`, counted by the time source. The entries already cached
+    /// are scheduled right away.
+    ///
+    /// # Arguments
+    /// * `resolution` - The length of a tick of the wheel.
+    ///
+    /// # Panics
+    /// If `resolution` is zero.
+    pub fn enable_timing_wheel(&self, resolution: Duration) {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_wheel(Some(resolution));
+    }
 
-    /// Inserts a key-value pair into the cache.
-    ///
-    /// If the key already exists, its value will be updated.
+    /// Detaches the timing wheel, if any. Expired entries are then only
+    /// reclaimed by lookups, `purge_expired` and the log head.
+    pub fn disable_timing_wheel(&self) {
+        let mut inner = self.inner.borrow_mut();
+        inner.set_wheel(None);
+    }
+
+    /// Advances the timing wheel to `now` and removes every entry whose
+    /// deadline has passed, firing the listener with `RemovalCause::Expired`
+    /// for each. Without a timing wheel this does nothing.
     ///
     /// # Arguments
-    /// * `key` - The key to insert.
-    /// * `value` - The value associated with the key.
-    pub fn insert(&self, key: K, value: V) {
-        let mut item = LogItem::new();
-        item.key = key;
-        item.value = value;
+    /// * `now` - The current time, as read from the table's time source.
+    ///
+    /// # Returns
+    /// The number of entries removed.
+    pub fn tick(&self, now: Instant) -> usize {
         let mut inner = self.inner.borrow_mut();
-        inner.insert(item);
+        inner.tick(now)
     }
 
-    /// Retrieves the value associated with the given key from the cache.
+    /// Removes the cache entry associated with the given key.
+    ///
+    /// The value is moved out of the log, so the memory it holds is handed
+    /// back to the caller immediately and the slot becomes reusable. An
+    /// expired entry is removed too, but reads as a miss.
     ///
     /// # Arguments
-    /// * `key` - The key to retrieve.
+    /// * `key` - The key to remove.
     ///
     /// # Returns
-    /// An `Option` containing the value if the key exists and is valid, `None` otherwise.
-    pub fn get(&self, key: &K) -> Option<V> {
-        let inner = self.inner.borrow();
-        inner.get(key)
+    /// An `Option` containing the removed value if the key was cached, `None` otherwise.
+    pub fn remove<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_hashed(self.hash_key(key), key)
+    }
+
+    /// Same as `remove`, for a key whose hash the caller already computed.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of `key`, as given to `insert_hashed` or returned by `hash_key`.
+    /// * `key` - The key to remove.
+    pub fn remove_hashed<Q>(&self, hash: u64, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let mut inner = self.inner.borrow_mut();
+        inner.remove(hash, key)
     }
 
     /// Invalidates the cache entry associated with the given key.
     ///
+    /// This is `remove` with the value dropped in place.
+    ///
     /// # Arguments
     /// * `key` - The key to invalidate.
-    pub fn invalid(&self, key: &K) {
-        let mut inner = self.inner.borrow_mut();
-        inner.invalid(key);
+    pub fn invalid<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove(key);
     }
 }
 
 impl<
-        K: Default + Hash + Eq + PartialEq + Clone,
-        V: Default + Clone,
+        K: Hash + Eq,
+        V,
         const L: usize,
         const B: usize,
-    > Default for CacheTable<K, V, L, B>
+        S: BuildHasher + Default,
+        P: Policy + Default,
+    > Default for CacheTable<K, V, L, B, S, P>
 {
     fn default() -> Self {
-        let inner = RefCell::new(InnerCache::new());
-        Self { inner }
+        Self::with_hasher(S::default())
     }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::CacheTable;
+    use super::{CacheTable, Insertion};
+    use crate::set::{EMPTY_FINGER, SIMD_SIZE};
+    use crate::{Clock, Lru, ManualTime, RemovalCause, S3Fifo, TimeSource, TinyLfu, WyHashBuilder};
+    use smallvec::smallvec;
+    use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+    use std::num::NonZeroU32;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Finds two distinct keys that land in the same set with the same
+    /// fingerprint for the given table.
+    fn colliding_keys<const L: usize, const B: usize>(
+        ctable: &CacheTable<u32, u32, L, B>,
+    ) -> (u32, u32) {
+        let inner = ctable.inner.borrow();
+        let place = |key: &u32| {
+            let hash = ctable.hasher.hash_one(key);
+            (inner.extract_set(hash), inner.extract_finger(hash))
+        };
+        let first = 1;
+        let target = place(&first);
+        let second = (first + 1..).find(|key| place(key) == target).unwrap();
+        (first, second)
+    }
 
     /// Tests the initialization of a CacheTable.
     #[test]
@@ -311,6 +1591,866 @@ mod tests {
         assert!(get_value.is_some());
         assert_eq!(get_value.unwrap(), value);
     }
+
+    /// Measures how often two keys placed in the same set share a fingerprint.
+    fn finger_collision_rate<const B: usize>() -> f64 {
+        let ctable = CacheTable::<u32, u32, 2, B>::with_seed(0);
+        let inner = ctable.inner.borrow();
+        let mut fingers = vec![[0u64; 256]; B];
+        for key in 0..(B * 16) as u32 {
+            let hash = ctable.hasher.hash_one(key);
+            let finger = inner.extract_finger(hash);
+            assert_ne!(finger, EMPTY_FINGER);
+            fingers[inner.extract_set(hash)][finger as usize] += 1;
+        }
+
+        let (mut same, mut pairs) = (0, 0);
+        for counts in &fingers {
+            let total: u64 = counts.iter().sum();
+            pairs += total * total.saturating_sub(1) / 2;
+            same += counts
+                .iter()
+                .map(|&count| count * count.saturating_sub(1) / 2)
+                .sum::<u64>();
+        }
+        same as f64 / pairs as f64
+    }
+
+    /// Tests that fingerprints stay independent of the set index for every
+    /// set count, so the false-positive rate stays near 1/255.
+    #[test]
+    fn finger_false_positive_rate() {
+        let rates = [
+            finger_collision_rate::<1>(),
+            finger_collision_rate::<32>(),
+            finger_collision_rate::<256>(),
+            finger_collision_rate::<1024>(),
+        ];
+        for rate in rates {
+            assert!(rate < 0.01, "fingerprint false-positive rate {rate}");
+        }
+    }
+
+    /// Tests that a key sharing a set and fingerprint with a cached key misses.
+    #[test]
+    fn finger_collision_miss() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        let (first, second) = colliding_keys(&ctable);
+
+        ctable.insert(first, 1);
+
+        assert_eq!(ctable.get(&first), Some(1));
+        assert_eq!(ctable.get(&second), None);
+    }
+
+    /// Tests that colliding keys keep their own values through inserts and updates.
+    #[test]
+    fn finger_collision_update() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        let (first, second) = colliding_keys(&ctable);
+
+        ctable.insert(first, 1);
+        ctable.insert(second, 2);
+        assert_eq!(ctable.get(&first), Some(1));
+        assert_eq!(ctable.get(&second), Some(2));
+
+        ctable.insert(second, 3);
+        assert_eq!(ctable.get(&first), Some(1));
+        assert_eq!(ctable.get(&second), Some(3));
+    }
+
+    /// Tests that invalidating one colliding key leaves the other reachable.
+    #[test]
+    fn finger_collision_invalidate() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        let (first, second) = colliding_keys(&ctable);
+
+        ctable.insert(first, 1);
+        ctable.insert(second, 2);
+        ctable.invalid(&first);
+
+        assert_eq!(ctable.get(&first), None);
+        assert_eq!(ctable.get(&second), Some(2));
+    }
+
+    /// Tests that the first pass over the log does not invalidate a live key
+    /// equal to the default key still held by unwritten log slots.
+    #[test]
+    fn first_wrap_keeps_default_key() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+
+        ctable.insert(0, 1);
+        ctable.insert(1, 2);
+        ctable.insert(2, 3);
+
+        assert_eq!(ctable.get(&0), Some(1));
+        assert_eq!(ctable.get(&1), Some(2));
+        assert_eq!(ctable.get(&2), Some(3));
+    }
+
+    /// Tests that evicting a log slot leaves alone a copy of the same key that
+    /// has since been re-inserted at a newer log position.
+    #[test]
+    fn wrap_keeps_reinserted_key() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+
+        ctable.insert(10, 1);
+        ctable.invalid(&10);
+        ctable.insert(10, 2);
+        ctable.insert(11, 3);
+        ctable.insert(12, 4);
+        ctable.insert(13, 5);
+
+        assert_eq!(ctable.get(&10), Some(2));
+        assert_eq!(ctable.get(&13), Some(5));
+    }
+
+    /// Tests that removing an entry hands back its value and frees the slot.
+    #[test]
+    fn remove() {
+        let ctable = CacheTable::<u32, Vec<u32>, 4, 32>::new();
+
+        ctable.insert(10, vec![10]);
+        assert_eq!(ctable.remove(&10), Some(vec![10]));
+        assert_eq!(ctable.get(&10), None);
+        assert_eq!(ctable.remove(&10), None);
+
+        let inner = ctable.inner.borrow();
+        assert!(inner.sets.iter().all(|set| set.valid_mask == 0));
+        assert!(inner.log.entries[0].is_none());
+    }
+
+    /// Tests reading a value in place and checking membership.
+    #[test]
+    fn get_with() {
+        let ctable = CacheTable::<u32, Vec<u32>, 4, 32>::new();
+        ctable.insert(10, vec![1, 2, 3]);
+
+        assert_eq!(ctable.get_with(&10, |value| value.len()), Some(3));
+        assert_eq!(ctable.get_with(&11, |value| value.len()), None);
+        assert!(ctable.contains_key(&10));
+        assert!(!ctable.contains_key(&11));
+    }
+
+    /// Tests looking up `String` keys through `&str`.
+    #[test]
+    fn borrowed_lookup() {
+        let ctable = CacheTable::<String, u32, 4, 32>::new();
+        ctable.insert("ten".to_string(), 10);
+
+        assert_eq!(ctable.get("ten"), Some(10));
+        assert_eq!(ctable.get_with("ten", |value| value + 1), Some(11));
+        assert!(ctable.contains_key("ten"));
+        assert!(!ctable.contains_key("eleven"));
+        assert_eq!(ctable.remove("ten"), Some(10));
+        assert!(!ctable.contains_key("ten"));
+    }
+
+    /// Tests that values without `Clone` can be cached and read in place.
+    #[test]
+    fn non_clone_value() {
+        #[derive(Default)]
+        struct Buffer(Vec<u8>);
+
+        let ctable = CacheTable::<u32, Buffer, 4, 32>::new();
+        ctable.insert(10, Buffer(vec![0; 16]));
+
+        assert_eq!(ctable.get_with(&10, |buffer| buffer.0.len()), Some(16));
+        assert_eq!(ctable.remove(&10).map(|buffer| buffer.0.len()), Some(16));
+    }
+
+    /// Tests the outcomes reported by insert.
+    #[test]
+    fn insert_outcome() {
+        let ctable = CacheTable::<u32, Vec<u32>, 2, 32>::new();
+
+        assert_eq!(ctable.insert(10, vec![1]), Insertion::Inserted);
+        assert_eq!(
+            ctable.insert(10, vec![2]),
+            Insertion::Updated {
+                old: vec![1],
+                evicted: smallvec![]
+            }
+        );
+        assert_eq!(ctable.insert(11, vec![3]), Insertion::Inserted);
+        assert_eq!(
+            ctable.insert(12, vec![4]),
+            Insertion::Evicted(smallvec![(10, vec![2])])
+        );
+        assert_eq!(ctable.get(&10), None);
+    }
+
+    /// Tests that the victim of a full set is reported along with the log head.
+    #[test]
+    fn insert_outcome_full_set() {
+        let ctable = CacheTable::<u32, u32, 32, 1>::new();
+
+        for key in 0..16 {
+            assert_eq!(ctable.insert(key, key), Insertion::Inserted);
+        }
+        assert_eq!(ctable.insert(16, 16), Insertion::Evicted(smallvec![(0, 0)]));
+        assert_eq!(ctable.get(&0), None);
+        assert_eq!(ctable.get(&16), Some(16));
+    }
+
+    /// Collects every notification fired by the table's listener.
+    fn record<const L: usize, const B: usize>(
+        ctable: &CacheTable<u32, u32, L, B>,
+    ) -> Arc<Mutex<Vec<(u32, u32, RemovalCause)>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        ctable.set_listener(move |key, value, cause| {
+            sink.lock().unwrap().push((*key, *value, cause));
+        });
+        events
+    }
+
+    /// Tests that the listener reports log wraps and explicit removals.
+    #[test]
+    fn listener() {
+        let ctable = CacheTable::<u32, u32, 2, 32>::new();
+        let events = record(&ctable);
+
+        ctable.insert(10, 1);
+        ctable.insert(11, 2);
+        ctable.insert(10, 3);
+        ctable.insert(12, 4);
+        ctable.remove(&11);
+        ctable.invalid(&12);
+        ctable.remove(&13);
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                (10, 3, RemovalCause::LogWrap),
+                (11, 2, RemovalCause::Explicit),
+                (12, 4, RemovalCause::Explicit),
+            ]
+        );
+    }
+
+    /// Tests that the listener reports reuse of a slot in a full set.
+    #[test]
+    fn listener_set_full() {
+        let ctable = CacheTable::<u32, u32, 32, 1>::new();
+        let events = record(&ctable);
+
+        for key in 0..17 {
+            ctable.insert(key, key);
+        }
+
+        assert_eq!(*events.lock().unwrap(), vec![(0, 0, RemovalCause::SetFull)]);
+    }
+
+    /// Tests that a cleared listener is no longer fired.
+    #[test]
+    fn clear_listener() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        let events = record(&ctable);
+
+        ctable.clear_listener();
+        ctable.insert(10, 1);
+        ctable.remove(&10);
+
+        assert!(events.lock().unwrap().is_empty());
+    }
+
+    /// Tests that the length follows inserts, updates, evictions and removals.
+    #[test]
+    fn len() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        assert!(ctable.is_empty());
+        assert_eq!(ctable.capacity(), 4);
+
+        for key in 0..3 {
+            ctable.insert(key, key);
+        }
+        ctable.insert(0, 10);
+        assert_eq!(ctable.len(), 3);
+
+        for key in 3..6 {
+            ctable.insert(key, key);
+        }
+        assert_eq!(ctable.len(), 4);
+
+        ctable.remove(&5);
+        ctable.remove(&5);
+        assert_eq!(ctable.len(), 3);
+        assert!(!ctable.is_empty());
+    }
+
+    /// Tests the per-set histogram and log usage of the occupancy report.
+    #[test]
+    fn occupancy() {
+        let ctable = CacheTable::<u32, u32, 32, 1>::new();
+        assert_eq!(ctable.capacity(), 16);
+
+        let report = ctable.occupancy();
+        assert_eq!(report.sets[0], 1);
+        assert_eq!(report.full_sets(), 0);
+        assert_eq!(report.log_referenced(), 0.0);
+
+        for key in 0..20 {
+            ctable.insert(key, key);
+        }
+
+        let report = ctable.occupancy();
+        assert_eq!(report.full_sets(), 1);
+        assert_eq!(report.live, 16);
+        assert_eq!(report.log_entries, 32);
+        assert_eq!(report.index_slots, 16);
+        assert_eq!(report.log_referenced(), 0.5);
+        assert_eq!(report.index_used(), 1.0);
+    }
+
+    /// Tests that flushing hides every entry and that the table refills.
+    #[test]
+    fn flush_all() {
+        let ctable = CacheTable::<u32, Vec<u32>, 4, 2>::new();
+        for key in 0..4 {
+            ctable.insert(key, vec![key]);
+        }
+
+        ctable.flush_all();
+        assert!(ctable.is_empty());
+        assert_eq!(ctable.iter().count(), 0);
+        assert_eq!(ctable.occupancy().sets[0], 2);
+        for key in 0..4 {
+            assert_eq!(ctable.get(&key), None);
+            assert_eq!(ctable.remove(&key), None);
+        }
+
+        assert_eq!(ctable.insert(1, vec![10]), Insertion::Inserted);
+        assert_eq!(ctable.insert(5, vec![50]), Insertion::Inserted);
+        assert_eq!(ctable.get(&1), Some(vec![10]));
+        assert_eq!(ctable.get(&5), Some(vec![50]));
+        assert_eq!(ctable.get(&2), None);
+        assert_eq!(ctable.len(), 2);
+
+        let inner = ctable.inner.borrow();
+        assert_eq!(inner.log.live(0).value, vec![10]);
+        assert_eq!(inner.log.live(2).value, vec![2]);
+    }
+
+    /// Tests that stale entries are reported to the listener as flushed once
+    /// the log head passes them or the table is cleared.
+    #[test]
+    fn flush_all_reclaims_lazily() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        let events = record(&ctable);
+        for key in 0..4 {
+            ctable.insert(key, key);
+        }
+
+        ctable.flush_all();
+        assert!(events.lock().unwrap().is_empty());
+        for key in 10..12 {
+            assert_eq!(ctable.insert(key, key), Insertion::Inserted);
+        }
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![(0, 0, RemovalCause::Flushed), (1, 1, RemovalCause::Flushed)]
+        );
+
+        ctable.flush_all();
+        ctable.clear();
+        let mut events = events.lock().unwrap().clone();
+        events.sort_by_key(|&(key, _, _)| key);
+        assert_eq!(
+            events,
+            [0, 1, 2, 3, 10, 11].map(|key| (key, key, RemovalCause::Flushed))
+        );
+        assert!(ctable.is_empty());
+    }
+
+    /// Tests that clearing drops every entry right away.
+    #[test]
+    fn clear() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        let events = record(&ctable);
+        for key in 0..3 {
+            ctable.insert(key, key);
+        }
+
+        ctable.clear();
+        assert!(ctable.is_empty());
+        assert_eq!(ctable.get(&0), None);
+        assert_eq!(events.lock().unwrap().len(), 3);
+
+        ctable.insert(5, 5);
+        assert_eq!(ctable.get(&5), Some(5));
+        assert_eq!(ctable.len(), 1);
+    }
+
+    /// Tests that a removed key can be inserted again.
+    #[test]
+    fn remove_reinsert() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+
+        ctable.insert(10, 1);
+        ctable.insert(11, 2);
+        ctable.remove(&10);
+        ctable.insert(10, 3);
+
+        assert_eq!(ctable.get(&10), Some(3));
+        assert_eq!(ctable.get(&11), Some(2));
+    }
+
+    /// Tests that the log evicts its oldest entry once it wraps.
+    #[test]
+    fn wrap_evicts_oldest() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+
+        for key in 0..5 {
+            ctable.insert(key, key);
+        }
+
+        assert_eq!(ctable.get(&0), None);
+        for key in 1..5 {
+            assert_eq!(ctable.get(&key), Some(key));
+        }
+    }
+
+    /// Tests that the seed, and only the seed, decides set placement.
+    #[test]
+    fn seeded_placement() {
+        let placement = |ctable: &CacheTable<u32, u32, 64, 64>| -> Vec<usize> {
+            let inner = ctable.inner.borrow();
+            (0..64)
+                .map(|key| inner.extract_set(ctable.hasher.hash_one(key)))
+                .collect()
+        };
+
+        let first = CacheTable::with_seed(1);
+        assert_eq!(placement(&first), placement(&CacheTable::with_seed(1)));
+        assert_ne!(placement(&first), placement(&CacheTable::with_seed(2)));
+        assert_ne!(placement(&CacheTable::new()), placement(&CacheTable::new()));
+    }
+
+    /// Tests keys and values without a default, and that the log tells written
+    /// entries from empty ones.
+    #[test]
+    fn no_default() {
+        let ctable = CacheTable::<NonZeroU32, Arc<str>, 4, 32>::new();
+        let key = |n| NonZeroU32::new(n).unwrap();
+
+        assert!(ctable
+            .inner
+            .borrow()
+            .log
+            .entries
+            .iter()
+            .all(Option::is_none));
+        for n in 1..6 {
+            ctable.insert(key(n), Arc::from(n.to_string()));
+        }
+        assert_eq!(ctable.get(&key(1)), None);
+        assert_eq!(ctable.get(&key(5)).as_deref(), Some("5"));
+
+        ctable.remove(&key(5));
+        let inner = ctable.inner.borrow();
+        let written = inner.log.entries.iter().filter(|entry| entry.is_some());
+        assert_eq!(written.count(), 3);
+    }
+
+    /// Tests that batched lookups and inserts match their one-by-one versions.
+    #[test]
+    fn batch() {
+        let ctable = CacheTable::<u32, u32, 8, 4>::new();
+
+        let inserted = ctable.insert_many((0..9).map(|key| (key, key * 10)));
+        assert_eq!(inserted.len(), 9);
+        assert!(inserted[..8]
+            .iter()
+            .all(|outcome| *outcome == Insertion::Inserted));
+        assert_eq!(inserted[8], Insertion::Evicted(smallvec![(0, 0)]));
+
+        assert_eq!(
+            ctable.insert_many([(3, 31), (20, 200)]),
+            vec![
+                Insertion::Updated {
+                    old: 30,
+                    evicted: smallvec![]
+                },
+                Insertion::Evicted(smallvec![(1, 10)])
+            ]
+        );
+        assert_eq!(
+            ctable.get_many(&[3, 1, 20, 7, 99]),
+            vec![Some(31), None, Some(200), Some(70), None]
+        );
+        assert!(ctable.get_many::<u32>(&[]).is_empty());
+    }
+
+    /// Tests the operations that take a precomputed hash.
+    #[test]
+    fn hashed() {
+        let ctable = CacheTable::<String, u32, 4, 32>::new();
+        let hash = ctable.hash_key("ten");
+        assert_eq!(hash, ctable.hash_key(&"ten".to_string()));
+
+        ctable.insert_hashed(hash, "ten".to_string(), 10);
+        assert_eq!(ctable.get("ten"), Some(10));
+        assert_eq!(ctable.get_hashed(hash, "ten"), Some(10));
+
+        let router_hash = 42;
+        ctable.insert_hashed(router_hash, "eleven".to_string(), 11);
+        assert_eq!(ctable.get_hashed(router_hash, "eleven"), Some(11));
+        assert_eq!(ctable.remove_hashed(router_hash, "eleven"), Some(11));
+        assert_eq!(ctable.remove_hashed(hash, "ten"), Some(10));
+        assert!(ctable.is_empty());
+    }
+
+    /// Tests that the policy, not the insertion order, picks the victim of a
+    /// full set, and that hits through any lookup reach it.
+    #[test]
+    fn policy() {
+        let fifo = CacheTable::<u32, u32, 64, 1>::new();
+        let lru = CacheTable::<u32, u32, 64, 1, WyHashBuilder, Lru>::new();
+        for key in 0..16 {
+            fifo.insert(key, key);
+            lru.insert(key, key);
+        }
+
+        fifo.get(&0);
+        lru.get(&0);
+        lru.get_with(&1, |_| ());
+        *lru.entry(2).or_insert(0).unwrap() += 1;
+        lru.insert(3, 3);
+        assert_eq!(fifo.insert(16, 16), Insertion::Evicted(smallvec![(0, 0)]));
+        assert_eq!(lru.insert(16, 16), Insertion::Evicted(smallvec![(4, 4)]));
+    }
+
+    /// Tests that CLOCK keeps a key that is read between inserts, while FIFO
+    /// loses it to the cold keys sharing its set.
+    #[test]
+    fn clock_keeps_hot_key() {
+        let fifo = CacheTable::<u32, u32, 64, 1>::new();
+        let clock = CacheTable::<u32, u32, 64, 1, WyHashBuilder, Clock>::new();
+        for key in 0..48 {
+            fifo.insert(key, key);
+            clock.insert(key, key);
+            fifo.get(&0);
+            clock.get(&0);
+        }
+
+        assert_eq!(fifo.get(&0), None);
+        assert_eq!(clock.get(&0), Some(0));
+        assert_eq!(clock.len(), SIMD_SIZE);
+    }
+
+    /// Tests that S3-FIFO keeps keys read while in the small queue through a
+    /// scan of cold keys, and sends keys evicted recently straight to the
+    /// main queue when they come back.
+    #[test]
+    fn s3fifo_scan_resistant() {
+        let fifo = CacheTable::<u32, u32, 64, 64>::with_seed(0);
+        let s3 = CacheTable::<u32, u32, 64, 64, WyHashBuilder, S3Fifo>::with_seed(0);
+        for key in 0..4 {
+            fifo.insert(key, key);
+            s3.insert(key, key);
+            fifo.get(&key);
+            s3.get(&key);
+        }
+        for key in 1000..1100 {
+            fifo.insert(key, key);
+            s3.insert(key, key);
+        }
+
+        for key in 0..4 {
+            assert_eq!(fifo.get(&key), None);
+            assert_eq!(s3.get(&key), Some(key));
+        }
+        assert!(!s3.contains_key(&1090));
+        s3.insert(1090, 1090);
+        for key in 2000..2020 {
+            s3.insert(key, key);
+        }
+        assert_eq!(s3.get(&1090), Some(1090));
+        assert!(!s3.contains_key(&2000));
+    }
+
+    /// Tests that with an admission filter a scan of cold keys is rejected
+    /// once the log is full, while a key seen often enough gets in.
+    #[test]
+    fn admission() {
+        let ctable = CacheTable::<u32, u32, 64, 64>::with_seed(0);
+        ctable.set_admission(TinyLfu::new(1024));
+        for key in 0..32 {
+            ctable.insert(key, key);
+            for _ in 0..3 {
+                ctable.get(&key);
+            }
+        }
+        for key in 100..132 {
+            assert_eq!(ctable.insert(key, key), Insertion::Inserted);
+        }
+
+        for key in 1000..1200 {
+            assert_eq!(ctable.insert(key, key), Insertion::Rejected(key, key));
+        }
+        assert!((0..32).all(|key| ctable.contains_key(&key)));
+
+        for _ in 0..5 {
+            ctable.get(&5000);
+        }
+        assert_eq!(
+            ctable.insert(5000, 1),
+            Insertion::Evicted(smallvec![(0, 0)])
+        );
+
+        ctable.clear_admission();
+        assert_eq!(
+            ctable.insert(1000, 1000),
+            Insertion::Evicted(smallvec![(1, 1)])
+        );
+    }
+
+    /// Tests that a weight budget evicts the oldest entries until a new one
+    /// fits and rejects entries heavier than the whole budget.
+    #[test]
+    fn weight_budget() {
+        let ctable = CacheTable::<u32, Vec<u8>, 16, 16>::with_seed(0);
+        ctable.set_weigher(|_: &u32, value: &Vec<u8>| value.len() as u32, 100);
+        ctable.insert(1, vec![1; 40]);
+        ctable.insert(2, vec![2; 40]);
+        assert_eq!(ctable.weight(), 80);
+
+        assert_eq!(
+            ctable.insert(3, vec![3; 40]),
+            Insertion::Evicted(smallvec![(1, vec![1; 40])])
+        );
+        assert_eq!(ctable.weight(), 80);
+        assert_eq!(
+            ctable.insert(4, vec![4; 101]),
+            Insertion::Rejected(4, vec![4; 101])
+        );
+
+        assert_eq!(
+            ctable.insert(3, vec![3; 60]),
+            Insertion::Updated {
+                old: vec![3; 40],
+                evicted: smallvec![]
+            }
+        );
+        assert_eq!(ctable.weight(), 100);
+        assert_eq!(
+            ctable.insert(3, vec![3; 70]),
+            Insertion::Updated {
+                old: vec![3; 60],
+                evicted: smallvec![(2, vec![2; 40])]
+            }
+        );
+        assert_eq!(ctable.weight(), 70);
+        assert!(!ctable.contains_key(&2));
+
+        // The updated entry is the oldest one, and is kept all the same.
+        ctable.insert(3, vec![3; 10]);
+        ctable.insert(5, vec![5; 30]);
+        assert_eq!(
+            ctable.insert(3, vec![3; 100]),
+            Insertion::Updated {
+                old: vec![3; 10],
+                evicted: smallvec![(5, vec![5; 30])]
+            }
+        );
+        assert_eq!(ctable.get(&3).map(|value| value.len()), Some(100));
+        assert_eq!(ctable.weight(), 100);
+
+        // An update heavier than the whole budget removes the cached value.
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        ctable.set_listener(move |key: &u32, _: &Vec<u8>, cause| {
+            sink.lock().unwrap().push((*key, cause))
+        });
+        assert_eq!(
+            ctable.insert(3, vec![3; 200]),
+            Insertion::Rejected(3, vec![3; 200])
+        );
+        assert_eq!(ctable.get(&3), None);
+        assert_eq!(*events.lock().unwrap(), vec![(3, RemovalCause::Weight)]);
+        assert_eq!(ctable.weight(), 0);
+        ctable.insert(3, vec![3; 10]);
+
+        ctable.remove(&3);
+        assert_eq!(ctable.weight(), 0);
+    }
+
+    /// Tests that setting a budget weighs the cached entries and evicts the
+    /// oldest ones, and that vacant entries are weighed too.
+    #[test]
+    fn weigher_on_full_table() {
+        let ctable = CacheTable::<u32, Vec<u8>, 16, 16>::with_seed(0);
+        for key in 0..8 {
+            ctable.insert(key, vec![0; 10]);
+        }
+        ctable.set_weigher(|_: &u32, value: &Vec<u8>| value.len() as u32, 50);
+        assert_eq!(ctable.weight(), 50);
+        assert_eq!(ctable.len(), 5);
+        assert!(!ctable.contains_key(&2));
+
+        ctable.entry(8).or_insert(vec![0; 10]).unwrap();
+        assert_eq!(ctable.len(), 5);
+        assert!(!ctable.contains_key(&3));
+
+        ctable.clear_weigher();
+        assert_eq!(ctable.weight(), 0);
+        ctable.insert(9, vec![0; 10]);
+        assert_eq!(ctable.len(), 6);
+    }
+
+    /// Tests that entries read as misses once their time-to-live has passed,
+    /// and that the lookup reclaims their slot.
+    #[test]
+    fn ttl() {
+        let ctable = CacheTable::<u32, u32, 16, 16>::new();
+        let time = ManualTime::new();
+        ctable.set_time_source(time.clone());
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        ctable.set_listener(move |key, _, cause| sink.lock().unwrap().push((*key, cause)));
+
+        ctable.insert_with_ttl(1, 10, Duration::from_secs(5));
+        ctable.insert(2, 20);
+        ctable.set_default_ttl(Duration::from_secs(10));
+        ctable.insert(3, 30);
+        time.advance(Duration::from_secs(5));
+
+        assert_eq!(ctable.get(&1), None);
+        assert_eq!(ctable.len(), 2);
+        assert_eq!(ctable.get(&2), Some(20));
+        assert_eq!(ctable.get(&3), Some(30));
+        assert_eq!(*events.lock().unwrap(), vec![(1, RemovalCause::Expired)]);
+
+        time.advance(Duration::from_secs(5));
+        assert!(!ctable.contains_key(&3));
+        assert_eq!(ctable.len(), 1);
+        ctable.clear_default_ttl();
+        assert_eq!(
+            ctable.insert(2, 21),
+            Insertion::Updated {
+                old: 20,
+                evicted: smallvec![]
+            }
+        );
+        assert_eq!(ctable.get(&2), Some(21));
+    }
+
+    /// Tests that a time-to-live too long for an `Instant` never expires
+    /// instead of panicking.
+    #[test]
+    fn ttl_overflow() {
+        let ctable = CacheTable::<u32, u32, 16, 16>::new();
+        let time = ManualTime::new();
+        ctable.set_time_source(time.clone());
+        ctable.set_default_ttl(Duration::from_secs(1));
+        ctable.insert_with_ttl(1, 10, Duration::MAX);
+        ctable.set_default_ttl(Duration::MAX);
+        ctable.insert(2, 20);
+        time.advance(Duration::from_secs(1 << 40));
+
+        assert_eq!(ctable.get(&1), Some(10));
+        assert_eq!(ctable.get(&2), Some(20));
+        assert_eq!(ctable.purge_expired(), 0);
+    }
+
+    /// Tests that expired entries are swept by `purge_expired` and replaced
+    /// rather than updated by inserts and entries.
+    #[test]
+    fn purge_expired() {
+        let ctable = CacheTable::<u32, u32, 16, 16>::new();
+        let time = ManualTime::new();
+        ctable.set_time_source(time.clone());
+        ctable.set_default_ttl(Duration::from_secs(1));
+        for key in 0..6 {
+            ctable.insert(key, key);
+        }
+        ctable.insert_with_ttl(6, 6, Duration::from_secs(2));
+        time.advance(Duration::from_secs(1));
+
+        assert_eq!(ctable.insert(0, 1), Insertion::Inserted);
+        assert_eq!(*ctable.entry(1).or_insert(2).unwrap(), 2);
+        assert_eq!(ctable.iter().count(), 3);
+        assert_eq!(ctable.purge_expired(), 4);
+        assert_eq!(ctable.len(), 3);
+        assert_eq!(ctable.purge_expired(), 0);
+    }
+
+    /// Tests that ticking the timing wheel removes exactly the entries whose
+    /// deadline has passed, and ignores entries that were overwritten.
+    #[test]
+    fn timing_wheel() {
+        let ctable = CacheTable::<u32, u32, 64, 16>::new();
+        let time = ManualTime::new();
+        ctable.set_time_source(time.clone());
+        ctable.insert_with_ttl(0, 0, Duration::from_secs(1));
+        ctable.enable_timing_wheel(Duration::from_millis(10));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        ctable.set_listener(move |key, _, cause| sink.lock().unwrap().push((*key, cause)));
+
+        for key in 1..20 {
+            ctable.insert_with_ttl(key, key, Duration::from_secs(key as u64));
+        }
+        ctable.insert(20, 20);
+        ctable.insert_with_ttl(3, 30, Duration::from_secs(100));
+
+        time.advance(Duration::from_secs(4));
+        assert_eq!(ctable.tick(time.now()), 4);
+        let mut expired = events.lock().unwrap().clone();
+        expired.sort_by_key(|&(key, _)| key);
+        assert_eq!(
+            expired,
+            [0, 1, 2, 4].map(|key| (key, RemovalCause::Expired))
+        );
+        assert_eq!(ctable.get(&3), Some(30));
+
+        time.advance(Duration::from_secs(1000));
+        assert_eq!(ctable.tick(time.now()), 16);
+        assert_eq!(ctable.len(), 1);
+        assert_eq!(ctable.tick(time.now()), 0);
+
+        ctable.disable_timing_wheel();
+        ctable.insert_with_ttl(21, 21, Duration::from_secs(1));
+        time.advance(Duration::from_secs(1));
+        assert_eq!(ctable.tick(time.now()), 0);
+        assert_eq!(ctable.purge_expired(), 1);
+    }
+
+    /// A hasher for keys that already are hashes.
+    #[derive(Default)]
+    struct IdentityHasher(u64);
+
+    impl Hasher for IdentityHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, _: &[u8]) {
+            unreachable!()
+        }
+
+        fn write_u64(&mut self, n: u64) {
+            self.0 = n;
+        }
+    }
+
+    /// Tests that a custom hasher decides the set of every key: hashes that
+    /// differ only above the bits mixed into the index of 32 sets share one.
+    #[test]
+    fn custom_hasher() {
+        let ctable = CacheTable::<u64, u64, 64, 32, BuildHasherDefault<IdentityHasher>>::new();
+
+        for key in 1..=SIMD_SIZE as u64 {
+            assert_eq!(ctable.insert(key << 37, key), Insertion::Inserted);
+        }
+        assert_eq!(ctable.occupancy().full_sets(), 1);
+        assert_eq!(
+            ctable.insert(17 << 37, 17),
+            Insertion::Evicted(smallvec![(1 << 37, 1)])
+        );
+        assert_eq!(ctable.get(&(2 << 37)), Some(2));
+    }
 }
 
 /* cachetable.rs ends here */
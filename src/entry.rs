@@ -0,0 +1,419 @@
+/* entry.rs --- ENTRY
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::cachetable::{Evictions, InnerCache};
+use crate::kv::LogItem;
+use crate::listener::RemovalCause;
+use crate::policy::{Fifo, Policy};
+use std::cell::RefMut;
+use std::hash::Hash;
+
+/// A view into a single entry of a `CacheTable`, which may either be vacant
+/// or occupied.
+///
+/// It is returned by `CacheTable::entry` and keeps the table mutably borrowed
+/// for as long as it lives.
+pub enum Entry<'a, K, V, P = Fifo> {
+    /// The key is cached.
+    Occupied(OccupiedEntry<'a, K, V, P>),
+    /// The key is not cached.
+    Vacant(VacantEntry<'a, K, V, P>),
+}
+
+/// A view into an occupied entry of a `CacheTable`.
+///
+/// It remembers the set and slot found by the probe, so reading, updating or
+/// removing the entry does not hash the key again.
+pub struct OccupiedEntry<'a, K, V, P = Fifo> {
+    inner: RefMut<'a, InnerCache<K, V, P>>,
+    set: usize,
+    slot: usize,
+}
+
+/// A view into a vacant entry of a `CacheTable`.
+///
+/// It owns the key together with its hash, set and fingerprint, so inserting
+/// into it skips the probe that a plain `insert` would do.
+pub struct VacantEntry<'a, K, V, P = Fifo> {
+    inner: RefMut<'a, InnerCache<K, V, P>>,
+    key: K,
+    hash: u64,
+    set: usize,
+    finger: u8,
+}
+
+impl<'a, K: Hash + Eq, V, P: Policy> Entry<'a, K, V, P> {
+    /// Returns a reference to the key of this entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts `value` if the entry is vacant and returns a mutable reference
+    /// to the value in the entry.
+    ///
+    /// # Returns
+    /// A reference to the value in the entry, or the key and `value`, with
+    /// nothing evicted, if the entry is vacant and `value` weighs more than
+    /// the whole weight budget.
+    pub fn or_insert(self, value: V) -> Result<RefMut<'a, V>, (K, V)> {
+        self.or_insert_with(|| value)
+    }
+
+    /// Inserts the result of `f` if the entry is vacant and returns a mutable
+    /// reference to the value in the entry. `f` is only called on a miss.
+    ///
+    /// # Returns
+    /// As `or_insert`.
+    pub fn or_insert_with<F: FnOnce() -> V>(self, f: F) -> Result<RefMut<'a, V>, (K, V)> {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => entry.insert(f()),
+        }
+    }
+
+    /// Like `or_insert_with`, but `f` receives the key to compute the value from.
+    ///
+    /// # Returns
+    /// As `or_insert`.
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, f: F) -> Result<RefMut<'a, V>, (K, V)> {
+        match self {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let value = f(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /// Inserts the default value if the entry is vacant and returns a mutable
+    /// reference to the value in the entry.
+    ///
+    /// # Returns
+    /// As `or_insert`.
+    pub fn or_default(self) -> Result<RefMut<'a, V>, (K, V)>
+    where
+        V: Default,
+    {
+        self.or_insert_with(V::default)
+    }
+
+    /// Calls `f` on the value if the entry is occupied, and returns the entry
+    /// for further chaining.
+    ///
+    /// With a weight budget, the value is weighed again afterwards and the
+    /// oldest other entries are evicted until the budget holds. If the value
+    /// alone weighs more than the whole budget, it is removed and the entry
+    /// returned vacant.
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        let Entry::Occupied(mut entry) = self else {
+            return self;
+        };
+        f(entry.get_mut());
+        match entry.inner.reweigh(entry.set, entry.slot) {
+            None => Entry::Occupied(entry),
+            Some(item) => {
+                let finger = entry.inner.extract_finger(item.hash);
+                Entry::Vacant(VacantEntry::new(
+                    entry.inner,
+                    item.key,
+                    item.hash,
+                    entry.set,
+                    finger,
+                ))
+            }
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, P: Policy> OccupiedEntry<'a, K, V, P> {
+    pub(crate) fn new(inner: RefMut<'a, InnerCache<K, V, P>>, set: usize, slot: usize) -> Self {
+        Self { inner, set, slot }
+    }
+
+    /// Returns the log item this entry points at.
+    fn item(&self) -> &LogItem<K, V> {
+        self.inner
+            .log
+            .live(self.inner.position(self.set, self.slot))
+    }
+
+    /// Returns a reference to the key of this entry.
+    pub fn key(&self) -> &K {
+        &self.item().key
+    }
+
+    /// Returns a reference to the cached value.
+    pub fn get(&self) -> &V {
+        &self.item().value
+    }
+
+    /// Returns a mutable reference to the cached value.
+    ///
+    /// The value is not weighed again after it changes; replace it with
+    /// `insert`, or change it with `Entry::and_modify`, to keep a weight
+    /// budget.
+    pub fn get_mut(&mut self) -> &mut V {
+        let pos = self.inner.position(self.set, self.slot);
+        &mut self.inner.log.live_mut(pos).value
+    }
+
+    /// Converts the entry into a mutable reference to the cached value that
+    /// keeps the table borrowed. As with `get_mut`, the value is not weighed
+    /// again.
+    pub fn into_mut(self) -> RefMut<'a, V> {
+        let pos = self.inner.position(self.set, self.slot);
+        RefMut::map(self.inner, |inner| &mut inner.log.live_mut(pos).value)
+    }
+
+    /// Replaces the cached value and returns the previous one.
+    ///
+    /// With a weight budget, the oldest other entries are evicted until the
+    /// new value fits; the listener is told about them.
+    ///
+    /// # Returns
+    /// The previous value, or `value` itself, with the entry left as it was,
+    /// if it weighs more than the whole weight budget.
+    pub fn insert(&mut self, value: V) -> Result<V, V> {
+        if !self.inner.fits(self.key(), &value) {
+            return Err(value);
+        }
+        let old = std::mem::replace(self.get_mut(), value);
+        self.inner.reweigh(self.set, self.slot);
+        Ok(old)
+    }
+
+    /// Removes the entry from the table and returns its value.
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Removes the entry from the table and returns its key and value.
+    pub fn remove_entry(mut self) -> (K, V) {
+        let item = self
+            .inner
+            .remove_slot(self.set, self.slot, RemovalCause::Explicit);
+        (item.key, item.value)
+    }
+}
+
+impl<'a, K: Hash + Eq, V, P: Policy> VacantEntry<'a, K, V, P> {
+    pub(crate) fn new(
+        inner: RefMut<'a, InnerCache<K, V, P>>,
+        key: K,
+        hash: u64,
+        set: usize,
+        finger: u8,
+    ) -> Self {
+        Self {
+            inner,
+            key,
+            hash,
+            set,
+            finger,
+        }
+    }
+
+    /// Returns a reference to the key that would be inserted.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Takes ownership of the key without inserting anything.
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// Inserts `value` under the entry's key and returns a mutable reference
+    /// to it. Like `CacheTable::insert`, this may evict the oldest entry; the
+    /// evicted entries are dropped.
+    ///
+    /// # Returns
+    /// A reference to the inserted value, or the key and value, with nothing
+    /// evicted, if the value weighs more than the whole weight budget. Unlike
+    /// `CacheTable::insert`, the admission filter is not consulted.
+    pub fn insert(mut self, value: V) -> Result<RefMut<'a, V>, (K, V)> {
+        let item = LogItem::new(self.key, value, self.hash);
+        match self
+            .inner
+            .insert_new(self.set, self.finger, item, &mut Evictions::new())
+        {
+            Ok(pos) => Ok(RefMut::map(self.inner, |inner| {
+                &mut inner.log.live_mut(pos).value
+            })),
+            Err(item) => Err((item.key, item.value)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CacheTable, Entry};
+
+    /// Tests that `or_insert_with` fills a miss and leaves a hit untouched.
+    #[test]
+    fn or_insert_with() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        let mut calls = 0;
+
+        *ctable
+            .entry(10)
+            .or_insert_with(|| {
+                calls += 1;
+                1
+            })
+            .unwrap() += 1;
+        let value = *ctable
+            .entry(10)
+            .or_insert_with(|| {
+                calls += 1;
+                5
+            })
+            .unwrap();
+
+        assert_eq!(value, 2);
+        assert_eq!(calls, 1);
+        assert_eq!(ctable.get(&10), Some(2));
+    }
+
+    /// Tests that `and_modify` only runs on occupied entries.
+    #[test]
+    fn and_modify() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+
+        ctable
+            .entry(10)
+            .and_modify(|value| *value += 1)
+            .or_insert(1)
+            .unwrap();
+        assert_eq!(ctable.get(&10), Some(1));
+
+        ctable
+            .entry(10)
+            .and_modify(|value| *value += 1)
+            .or_insert(1)
+            .unwrap();
+        assert_eq!(ctable.get(&10), Some(2));
+    }
+
+    /// Tests the occupied and vacant variants directly.
+    #[test]
+    fn variants() {
+        let ctable = CacheTable::<u32, Vec<u32>, 4, 32>::new();
+
+        match ctable.entry(10) {
+            Entry::Vacant(entry) => {
+                assert_eq!(*entry.key(), 10);
+                entry.insert(vec![1]).unwrap().push(2);
+            }
+            Entry::Occupied(_) => unreachable!(),
+        }
+
+        match ctable.entry(10) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.get(), &vec![1, 2]);
+                assert_eq!(entry.insert(vec![3]), Ok(vec![1, 2]));
+                assert_eq!(entry.remove_entry(), (10, vec![3]));
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+
+        assert!(!ctable.contains_key(&10));
+        match ctable.entry(10) {
+            Entry::Vacant(entry) => assert_eq!(entry.into_key(), 10),
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert!(!ctable.contains_key(&10));
+    }
+
+    /// Tests that a vacant entry refuses a value heavier than the whole
+    /// weight budget without evicting anything.
+    #[test]
+    fn vacant_insert_too_heavy() {
+        let ctable = CacheTable::<u32, Vec<u8>, 16, 16>::new();
+        ctable.set_weigher(|_: &u32, value: &Vec<u8>| value.len() as u32, 100);
+        ctable.insert(1, vec![1; 50]);
+        ctable.insert(2, vec![2; 50]);
+
+        match ctable.entry(99) {
+            Entry::Vacant(entry) => {
+                assert_eq!(entry.insert(vec![0; 500]).err(), Some((99, vec![0; 500])))
+            }
+            Entry::Occupied(_) => unreachable!(),
+        }
+        assert_eq!(ctable.len(), 2);
+        assert_eq!(ctable.weight(), 100);
+
+        assert_eq!(
+            ctable.entry(99).or_insert(vec![0; 500]).err(),
+            Some((99, vec![0; 500]))
+        );
+        assert_eq!(ctable.len(), 2);
+        assert!(!ctable.contains_key(&99));
+    }
+
+    /// Tests that values replaced or changed through an occupied entry are
+    /// weighed again and keep the weight budget.
+    #[test]
+    fn occupied_reweighs() {
+        let ctable = CacheTable::<u32, Vec<u8>, 16, 16>::new();
+        ctable.set_weigher(|_: &u32, value: &Vec<u8>| value.len() as u32, 100);
+        ctable.insert(1, vec![1; 10]);
+        ctable.insert(2, vec![2; 40]);
+
+        match ctable.entry(1) {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.insert(vec![1; 5000]), Err(vec![1; 5000]));
+                assert_eq!(entry.insert(vec![1; 70]), Ok(vec![1; 10]));
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+        assert_eq!(ctable.weight(), 70);
+        assert!(!ctable.contains_key(&2));
+
+        ctable.entry(1).and_modify(|value| value.truncate(20));
+        assert_eq!(ctable.weight(), 20);
+        let entry = ctable.entry(1).and_modify(|value| value.resize(5000, 1));
+        assert!(matches!(entry, Entry::Vacant(_)));
+        drop(entry);
+        assert!(!ctable.contains_key(&1));
+        assert_eq!(ctable.weight(), 0);
+    }
+
+    /// Tests that filling vacant entries evicts like `insert` once the log wraps.
+    #[test]
+    fn vacant_insert_wraps() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+
+        for key in 0..5 {
+            ctable.entry(key).or_insert(key).unwrap();
+        }
+
+        assert!(!ctable.contains_key(&0));
+        for key in 1..5 {
+            assert_eq!(ctable.get(&key), Some(key));
+        }
+    }
+}
+
+/* entry.rs ends here */
@@ -0,0 +1,94 @@
+/* hash.rs --- HASH
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use wyhash2::WyHash;
+
+/// The default `BuildHasher` of a `CacheTable`, producing seeded WyHash
+/// hashers.
+///
+/// `WyHash` itself implements `BuildHasher`, but always with seed 0; this
+/// builder carries the seed its hashers start from. A builder made with
+/// `new` or `default` draws a random seed, so every table places keys in
+/// different sets and clients cannot precompute keys that collide.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WyHashBuilder {
+    seed: u64,
+}
+
+impl WyHashBuilder {
+    /// Creates a builder with a random seed.
+    ///
+    /// The seed comes from the randomly keyed `RandomState` of the standard
+    /// library, which is seeded by the operating system.
+    pub fn new() -> Self {
+        Self::with_seed(RandomState::new().build_hasher().finish())
+    }
+
+    /// Creates a builder whose hashers start from the given seed, for
+    /// reproducible placement.
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// Returns the seed of the hashers this builder produces.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl Default for WyHashBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildHasher for WyHashBuilder {
+    type Hasher = WyHash;
+
+    #[inline]
+    fn build_hasher(&self) -> WyHash {
+        WyHash::with_seed(self.seed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WyHashBuilder;
+    use std::hash::BuildHasher;
+
+    /// Tests that builders draw different seeds, and that a seed fixes the hash.
+    #[test]
+    fn seeds() {
+        assert_ne!(WyHashBuilder::new().seed(), WyHashBuilder::new().seed());
+        assert_eq!(
+            WyHashBuilder::with_seed(7).hash_one(10u32),
+            WyHashBuilder::with_seed(7).hash_one(10u32)
+        );
+        assert_ne!(
+            WyHashBuilder::with_seed(7).hash_one(10u32),
+            WyHashBuilder::with_seed(8).hash_one(10u32)
+        );
+    }
+}
+
+/* hash.rs ends here */
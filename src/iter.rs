@@ -0,0 +1,265 @@
+/* iter.rs --- ITER
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::cachetable::InnerCache;
+use crate::listener::RemovalCause;
+use crate::policy::{Fifo, Policy};
+use std::cell::{Ref, RefMut};
+use std::hash::Hash;
+
+/// The order in which the iterators of a `CacheTable` visit live entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IterOrder {
+    /// Set by set, and by slot within a set. This only reads the sets and the
+    /// live log entries.
+    #[default]
+    BySet,
+    /// Oldest first, walking the log from its head. This reads every log
+    /// entry, live or not. When the policy splits the log into several
+    /// queues, they are walked one after the other, each oldest first.
+    ByAge,
+}
+
+/// A position in a walk over the live entries of an `InnerCache`.
+enum Cursor {
+    /// The next set to load and the slots of the current set left to visit.
+    Set { set: usize, mask: u16 },
+    /// The number of log entries visited so far, in age order.
+    Log { step: usize },
+}
+
+impl Cursor {
+    fn new(order: IterOrder) -> Self {
+        match order {
+            IterOrder::BySet => Cursor::Set { set: 0, mask: 0 },
+            IterOrder::ByAge => Cursor::Log { step: 0 },
+        }
+    }
+
+    /// Advances to the next live entry and returns its set and slot.
+    fn next<K: Hash + Eq, V, P: Policy>(
+        &mut self,
+        inner: &InnerCache<K, V, P>,
+    ) -> Option<(usize, usize)> {
+        match self {
+            Cursor::Set { set, mask } => {
+                while *mask == 0 {
+                    if *set == inner.sets.len() {
+                        return None;
+                    }
+                    *mask = inner.live_mask(*set);
+                    *set += 1;
+                }
+                let slot = mask.trailing_zeros() as usize;
+                *mask &= *mask - 1;
+                Some((*set - 1, slot))
+            }
+            Cursor::Log { step } => {
+                let size = inner.log_size();
+                while *step < size {
+                    let pos = inner.log.nth_oldest(*step);
+                    *step += 1;
+                    if let Some(found) = inner.back_pointer(pos) {
+                        return Some(found);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+/// An iterator over the live entries of a `CacheTable`.
+///
+/// It keeps the table borrowed, and yields each key and value as a `Ref` into
+/// the log, so nothing is cloned. Expired entries are skipped. Inserting into
+/// or removing from the table while the iterator or any yielded `Ref` is
+/// alive panics.
+pub struct Iter<'a, K, V, P = Fifo> {
+    inner: Ref<'a, InnerCache<K, V, P>>,
+    cursor: Cursor,
+}
+
+impl<'a, K: Hash + Eq, V, P: Policy> Iter<'a, K, V, P> {
+    pub(crate) fn new(inner: Ref<'a, InnerCache<K, V, P>>, order: IterOrder) -> Self {
+        Self {
+            inner,
+            cursor: Cursor::new(order),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, P: Policy> Iterator for Iter<'a, K, V, P> {
+    type Item = (Ref<'a, K>, Ref<'a, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (set, slot) = loop {
+            let (set, slot) = self.cursor.next(&self.inner)?;
+            if !self.inner.expired(set, slot) {
+                break (set, slot);
+            }
+        };
+        let pos = self.inner.position(set, slot);
+        Some(Ref::map_split(Ref::clone(&self.inner), |inner| {
+            let item = inner.log.live(pos);
+            (&item.key, &item.value)
+        }))
+    }
+}
+
+/// An iterator over the keys of the live entries of a `CacheTable`.
+pub struct Keys<'a, K, V, P = Fifo>(pub(crate) Iter<'a, K, V, P>);
+
+impl<'a, K: Hash + Eq, V, P: Policy> Iterator for Keys<'a, K, V, P> {
+    type Item = Ref<'a, K>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(key, _)| key)
+    }
+}
+
+/// An iterator over the values of the live entries of a `CacheTable`.
+pub struct Values<'a, K, V, P = Fifo>(pub(crate) Iter<'a, K, V, P>);
+
+impl<'a, K: Hash + Eq, V, P: Policy> Iterator for Values<'a, K, V, P> {
+    type Item = Ref<'a, V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, value)| value)
+    }
+}
+
+/// A draining iterator that moves the live entries out of a `CacheTable`,
+/// oldest first.
+///
+/// Every entry is removed as it is yielded, firing the listener with
+/// `RemovalCause::Explicit`. Expired entries are removed with
+/// `RemovalCause::Expired` instead, without being yielded. Entries not yet
+/// yielded when the iterator is dropped are removed as well.
+pub struct Drain<'a, K: Hash + Eq, V, P: Policy = Fifo> {
+    inner: RefMut<'a, InnerCache<K, V, P>>,
+    cursor: Cursor,
+}
+
+impl<'a, K: Hash + Eq, V, P: Policy> Drain<'a, K, V, P> {
+    pub(crate) fn new(inner: RefMut<'a, InnerCache<K, V, P>>) -> Self {
+        Self {
+            inner,
+            cursor: Cursor::new(IterOrder::ByAge),
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, P: Policy> Iterator for Drain<'_, K, V, P> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (set, slot) = self.cursor.next(&self.inner)?;
+            if self.inner.expired(set, slot) {
+                self.inner.remove_slot(set, slot, RemovalCause::Expired);
+                continue;
+            }
+            let item = self.inner.remove_slot(set, slot, RemovalCause::Explicit);
+            return Some((item.key, item.value));
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, P: Policy> Drop for Drain<'_, K, V, P> {
+    fn drop(&mut self) {
+        self.for_each(drop);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CacheTable, IterOrder};
+
+    /// Tests that iteration skips evicted and removed entries.
+    #[test]
+    fn iter_live_only() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        for key in 0..6 {
+            ctable.insert(key, key * 10);
+        }
+        ctable.remove(&3);
+
+        let mut entries: Vec<_> = ctable.iter().map(|(key, value)| (*key, *value)).collect();
+        entries.sort();
+        assert_eq!(entries, vec![(2, 20), (4, 40), (5, 50)]);
+
+        let mut keys: Vec<_> = ctable.keys().map(|key| *key).collect();
+        keys.sort();
+        assert_eq!(keys, vec![2, 4, 5]);
+
+        let mut values: Vec<_> = ctable.values().map(|value| *value).collect();
+        values.sort();
+        assert_eq!(values, vec![20, 40, 50]);
+    }
+
+    /// Tests that walking by age starts at the oldest live entry.
+    #[test]
+    fn iter_by_age() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        for key in 0..6 {
+            ctable.insert(key, key);
+        }
+        ctable.insert(3, 30);
+
+        let keys: Vec<_> = ctable
+            .iter_in(IterOrder::ByAge)
+            .map(|(key, _)| *key)
+            .collect();
+        assert_eq!(keys, vec![2, 3, 4, 5]);
+    }
+
+    /// Tests that draining empties the table oldest first.
+    #[test]
+    fn drain() {
+        let ctable = CacheTable::<u32, Vec<u32>, 4, 32>::new();
+        for key in 0..5 {
+            ctable.insert(key, vec![key]);
+        }
+
+        let drained: Vec<_> = ctable.drain().collect();
+        assert_eq!(
+            drained,
+            vec![(1, vec![1]), (2, vec![2]), (3, vec![3]), (4, vec![4])]
+        );
+        assert!(ctable.is_empty());
+        assert_eq!(ctable.iter().count(), 0);
+    }
+
+    /// Tests that dropping a drain early still removes the rest.
+    #[test]
+    fn drain_drop() {
+        let ctable = CacheTable::<u32, u32, 4, 32>::new();
+        for key in 0..4 {
+            ctable.insert(key, key);
+        }
+
+        assert_eq!(ctable.drain().next(), Some((0, 0)));
+        assert!(ctable.is_empty());
+    }
+}
+
+/* iter.rs ends here */
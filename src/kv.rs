@@ -20,9 +20,16 @@
 */
 
 use std::fmt::Display;
+use std::time::Instant;
 
 /// A `LogItem` structure that holds a key-value pair.
 ///
+/// Each item also remembers where its index entry lives: `hash` selects the
+/// set and `slot` the way within it. Eviction uses this back-pointer to clear
+/// exactly the slot that points at the item, and only while it still does.
+/// `weight` is the item's share of the table's weight budget, if it has one,
+/// and `deadline` the time it expires at, if it has a time-to-live.
+///
 /// # Type Parameters
 /// - `K`: The type of the key.
 /// - `V`: The type of the value.
@@ -31,22 +38,24 @@ use std::fmt::Display;
 pub(crate) struct LogItem<K, V> {
     pub(crate) key: K,
     pub(crate) value: V,
+    pub(crate) hash: u64,
+    pub(crate) slot: usize,
+    pub(crate) weight: u32,
+    pub(crate) deadline: Option<Instant>,
 }
 
-impl<K: Default, V: Default> LogItem<K, V> {
-    /// Creates a new `LogItem` with default key and value.
-    pub(crate) fn new() -> Self {
-        Self::default()
-    }
-}
-
-impl<K: Default, V: Default> Default for LogItem<K, V> {
-    /// Provides a default implementation for `LogItem`.
-    /// Returns a `LogItem` with default key and value.
-    fn default() -> Self {
+impl<K, V> LogItem<K, V> {
+    /// Creates a new `LogItem` for a key-value pair and the hash of the key.
+    /// The slot is filled in once the item is placed in a set, and the weight
+    /// once it is weighed.
+    pub(crate) fn new(key: K, value: V, hash: u64) -> Self {
         Self {
-            value: V::default(),
-            key: K::default(),
+            key,
+            value,
+            hash,
+            slot: 0,
+            weight: 0,
+            deadline: None,
         }
     }
 }
@@ -21,13 +21,35 @@
 
 #![feature(portable_simd)]
 #![feature(thread_id_value)]
+mod admission;
+mod builder;
 mod cachetable;
+mod entry;
+mod hash;
+mod iter;
 mod kv;
+mod listener;
 mod log;
+mod occupancy;
+mod policy;
+mod prefetch;
 mod set;
 mod shard;
 mod shardedtable;
+mod time;
+mod weigher;
+mod wheel;
 
-pub use cachetable::CacheTable;
+pub use admission::TinyLfu;
+pub use builder::{BuildError, CacheTableBuilder};
+pub use cachetable::{CacheTable, DynCacheTable, Evictions, Insertion, DYNAMIC};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use hash::WyHashBuilder;
+pub use iter::{Drain, Iter, IterOrder, Keys, Values};
+pub use listener::RemovalCause;
+pub use occupancy::Occupancy;
+pub use policy::{Clock, Fifo, Lru, Policy, Reclaim, S3Fifo};
 pub use shardedtable::ShardedTable;
+pub use time::{ManualTime, MonotonicTime, TimeSource};
+pub use weigher::Weigher;
 /* lib.rs ends here */
@@ -0,0 +1,47 @@
+/* listener.rs --- LISTENER
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/// The reason an entry left a `CacheTable`, as reported to its listener.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RemovalCause {
+    /// The log head wrapped around onto the entry to make room for a new one.
+    LogWrap,
+    /// The entry's set was full and its slot was reused for a new key.
+    SetFull,
+    /// The entry was removed by `remove`, `invalid` or an occupied entry.
+    Explicit,
+    /// The entry was among the oldest ones evicted to keep the total weight
+    /// of the live entries within the table's weight budget.
+    Weight,
+    /// The entry outlived its time-to-live.
+    Expired,
+    /// The entry was invalidated by `flush_all`, and is reported once the
+    /// log head reaches it or the table is cleared.
+    Flushed,
+}
+
+/// A callback fired with the key, value and cause of every entry that leaves
+/// a table. It runs while the table is borrowed, so it must not call back
+/// into the same table.
+pub(crate) type Listener<K, V> = Box<dyn FnMut(&K, &V, RemovalCause) + Send>;
+
+/* listener.rs ends here */
@@ -22,38 +22,139 @@
 use crate::kv::LogItem;
 use std::fmt::Display;
 
-/// A `Log` structure that holds a fixed-size array of log items.
+/// A `Log` structure that holds a heap-allocated array of log entries.
 ///
 /// # Type Parameters
 /// - `Key`: The type of the key.
 /// - `Value`: The type of the value.
-/// - `LOG_SIZE`: The fixed size of the log.
 ///
 /// The `Log` is used to store `LogItem` instances, each containing a key-value pair.
-/// It provides a default implementation to initialize the log with default log items.
-pub(crate) struct Log<Key, Value, const LOG_SIZE: usize> {
-    pub(crate) entries: [LogItem<Key, Value>; LOG_SIZE],
+/// Its length is chosen at runtime. An entry that was never written, or whose
+/// item has been moved out, is `None`, so neither keys nor values need a
+/// default to fill the log with.
+///
+/// The entries are split into consecutive regions, each a circular FIFO
+/// queue with its own head. By default the whole log is a single queue.
+pub(crate) struct Log<Key, Value> {
+    pub(crate) entries: Box<[Option<LogItem<Key, Value>>]>,
+    queues: Box<[Queue]>,
+}
+
+/// A region of the log used as a circular FIFO queue.
+///
+/// `head` is both the oldest entry of the queue and the next one written.
+#[derive(Debug, Clone, Copy)]
+struct Queue {
+    start: usize,
+    end: usize,
+    head: usize,
 }
 
-impl<Key: Default, Value: Default, const LOG_SIZE: usize> Default for Log<Key, Value, LOG_SIZE> {
-    /// Provides a default implementation for `Log`.
-    /// Initializes the log with default log items.
-    fn default() -> Self {
+impl<Key, Value> Log<Key, Value> {
+    /// Creates a log of empty entries split into queues of the given lengths.
+    ///
+    /// # Panics
+    /// If a queue is empty.
+    pub(crate) fn new(queue_lens: &[usize]) -> Self {
+        let mut start = 0;
+        let queues = queue_lens
+            .iter()
+            .map(|&len| {
+                assert!(len > 0, "log queues must not be empty");
+                let queue = Queue {
+                    start,
+                    end: start + len,
+                    head: start,
+                };
+                start += len;
+                queue
+            })
+            .collect();
         Self {
-            entries: std::array::from_fn(|_| LogItem::default()),
+            entries: (0..start).map(|_| None).collect(),
+            queues,
+        }
+    }
+
+    /// Returns the head of `queue`: its oldest entry, written next.
+    #[inline]
+    pub(crate) fn head(&self, queue: usize) -> usize {
+        self.queues[queue].head
+    }
+
+    /// Moves the head of `queue` one entry forward, wrapping at its end.
+    #[inline]
+    pub(crate) fn advance(&mut self, queue: usize) {
+        let queue = &mut self.queues[queue];
+        queue.head += 1;
+        if queue.head == queue.end {
+            queue.head = queue.start;
+        }
+    }
+
+    /// Returns the position of the `n`th entry in age order, walking the
+    /// queues one after the other, each from its head.
+    #[inline]
+    pub(crate) fn nth_oldest(&self, mut n: usize) -> usize {
+        for queue in self.queues.iter() {
+            let len = queue.end - queue.start;
+            if n < len {
+                return queue.start + (queue.head - queue.start + n) % len;
+            }
+            n -= len;
+        }
+        panic!("log position out of range");
+    }
+
+    /// Returns the live item at `pos`.
+    ///
+    /// # Panics
+    /// If the entry is empty; only positions that a valid set slot points at
+    /// may be passed.
+    #[inline]
+    pub(crate) fn live(&self, pos: usize) -> &LogItem<Key, Value> {
+        self.entries[pos]
+            .as_ref()
+            .expect("a valid slot points at an empty log entry")
+    }
+
+    /// Mutable version of `live`.
+    #[inline]
+    pub(crate) fn live_mut(&mut self, pos: usize) -> &mut LogItem<Key, Value> {
+        self.entries[pos]
+            .as_mut()
+            .expect("a valid slot points at an empty log entry")
+    }
+
+    /// Moves the item at `pos` out of the log, leaving the entry empty.
+    #[inline]
+    pub(crate) fn take(&mut self, pos: usize) -> Option<LogItem<Key, Value>> {
+        self.entries[pos].take()
+    }
+
+    /// Empties every entry, dropping the items they hold, and moves every
+    /// queue head back to the start of its queue.
+    pub(crate) fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        for queue in self.queues.iter_mut() {
+            queue.head = queue.start;
         }
     }
 }
 
-impl<Key: Display, Value: Display, const LOG_SIZE: usize> Display for Log<Key, Value, LOG_SIZE> {
-    /// Formats the `Log` as a string, with each `LogItem` separated by a newline.
+impl<Key: Display, Value: Display> Display for Log<Key, Value> {
+    /// Formats the `Log` as a string, with each `LogItem` separated by a newline
+    /// and empty entries shown as `-`.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
             "{}",
             self.entries
                 .iter()
-                .map(|item| format!("{item}"))
+                .map(|entry| match entry {
+                    Some(item) => format!("{item}"),
+                    None => "-".to_string(),
+                })
                 .collect::<Vec<String>>()
                 .join("\n")
         )
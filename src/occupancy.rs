@@ -0,0 +1,56 @@
+/* occupancy.rs --- OCCUPANCY
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::set::SIMD_SIZE;
+
+/// A snapshot of how full a `CacheTable` is, used to size the log and the
+/// number of sets and to spot saturated sets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occupancy {
+    /// `sets[n]` is the number of sets with exactly `n` valid slots.
+    pub sets: [usize; SIMD_SIZE + 1],
+    /// The number of live entries, each referenced by one valid slot.
+    pub live: usize,
+    /// The number of entries in the log.
+    pub log_entries: usize,
+    /// The number of slots across all sets.
+    pub index_slots: usize,
+}
+
+impl Occupancy {
+    /// Returns the number of sets whose slots are all taken, where the next
+    /// new key evicts one of the set's own entries.
+    pub fn full_sets(&self) -> usize {
+        self.sets[SIMD_SIZE]
+    }
+
+    /// Returns the fraction of log entries still referenced by a valid slot.
+    pub fn log_referenced(&self) -> f64 {
+        self.live as f64 / self.log_entries as f64
+    }
+
+    /// Returns the fraction of set slots holding a live entry.
+    pub fn index_used(&self) -> f64 {
+        self.live as f64 / self.index_slots as f64
+    }
+}
+
+/* occupancy.rs ends here */
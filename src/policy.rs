@@ -0,0 +1,460 @@
+/* policy.rs --- POLICY
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use crate::set::SIMD_SIZE;
+use std::collections::{HashMap, VecDeque};
+
+/// Decides which entries a `CacheTable` evicts for new keys.
+///
+/// Every set has 16 slots. A new key takes a free slot of its set while there
+/// is one; once all 16 are taken, `choose_victim` picks the slot to reuse.
+/// The policy keeps whatever state it needs per set, indexed by set, and the
+/// table reports hits and inserts so the state can follow the workload.
+///
+/// The log is a set of FIFO queues, by default a single one spanning the
+/// whole log, so entries leave it in insertion order. A policy can split it
+/// with `log_queues`, pick the queue of each new entry with `queue_for`, and
+/// keep the oldest entry of a queue, in the same or another queue, when it is
+/// about to be overwritten with `reclaim`.
+pub trait Policy {
+    /// Allocates the state for a table with `sets` sets and a log of
+    /// `log_size` entries. Called once, before any other hook.
+    fn init(&mut self, sets: usize, log_size: usize);
+
+    /// Called when a lookup finds the entry in `slot` of `set`, stored at log
+    /// position `pos`.
+    fn on_hit(&mut self, set: usize, slot: usize, pos: usize);
+
+    /// Called when a new entry has been written to `slot` of `set` and to
+    /// log position `pos`.
+    fn on_insert(&mut self, set: usize, slot: usize, pos: usize);
+
+    /// Picks the slot of the full set `set` whose entry gets evicted.
+    /// The returned slot must be below 16.
+    fn choose_victim(&mut self, set: usize) -> usize;
+
+    /// Returns the lengths of the queues the log is split into, in log
+    /// order. They must be non-zero and add up to `log_size`.
+    fn log_queues(&self, log_size: usize) -> Vec<usize> {
+        vec![log_size]
+    }
+
+    /// Picks the queue a new entry whose key hashes to `hash` is written to.
+    fn queue_for(&mut self, _hash: u64) -> usize {
+        0
+    }
+
+    /// Decides the fate of the live entry at log position `pos`, the oldest
+    /// of `queue`, before the queue overwrites it. `hash` is its key's hash.
+    ///
+    /// Requeueing an entry must not go on forever: the table keeps asking
+    /// until an entry is evicted or a free position comes up.
+    fn reclaim(&mut self, _queue: usize, _pos: usize, _hash: u64) -> Reclaim {
+        Reclaim::Evict
+    }
+
+    /// Called when a requeued entry moves from log position `from` to `to`.
+    fn on_move(&mut self, _from: usize, _to: usize) {}
+}
+
+/// What happens to the oldest entry of a log queue that is about to be
+/// overwritten, as decided by `Policy::reclaim`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reclaim {
+    /// The entry is evicted, with `RemovalCause::LogWrap`.
+    Evict,
+    /// The entry is kept and becomes the newest entry of the given queue.
+    /// Requeueing into its own queue leaves it in place.
+    Requeue(usize),
+}
+
+/// Evicts the slots of a full set round-robin, which is first-in first-out
+/// as long as the set stays full. This is the default policy.
+#[derive(Debug, Clone, Default)]
+pub struct Fifo {
+    next: Box<[u8]>,
+}
+
+impl Policy for Fifo {
+    fn init(&mut self, sets: usize, _log_size: usize) {
+        self.next = vec![0; sets].into_boxed_slice();
+    }
+
+    #[inline]
+    fn on_hit(&mut self, _set: usize, _slot: usize, _pos: usize) {}
+
+    #[inline]
+    fn on_insert(&mut self, _set: usize, _slot: usize, _pos: usize) {}
+
+    #[inline]
+    fn choose_victim(&mut self, set: usize) -> usize {
+        let slot = self.next[set];
+        self.next[set] = (slot + 1) % SIMD_SIZE as u8;
+        slot as usize
+    }
+}
+
+/// Evicts the least recently used slot of a full set.
+///
+/// Each set keeps its 16 slots ordered by recency in a single `u64`, one
+/// nibble per slot with the most recently used slot in the lowest nibble, so
+/// a hit or an insert only shifts a few nibbles.
+#[derive(Debug, Clone, Default)]
+pub struct Lru {
+    order: Box<[u64]>,
+}
+
+impl Lru {
+    /// The recency order of a fresh set: slot 0 most recent, slot 15 least.
+    const INITIAL: u64 = 0xFEDC_BA98_7654_3210;
+
+    /// Moves `slot` to the most recently used end of `order`.
+    #[inline]
+    fn touch(order: u64, slot: usize) -> u64 {
+        let pos = (0..SIMD_SIZE)
+            .find(|pos| (order >> (4 * pos)) & 0xF == slot as u64)
+            .expect("every slot appears in the recency order");
+        let shift = 4 * pos;
+        let below = order & ((1 << shift) - 1);
+        let above = if pos == SIMD_SIZE - 1 {
+            0
+        } else {
+            order & (u64::MAX << (shift + 4))
+        };
+        above | (below << 4) | slot as u64
+    }
+}
+
+impl Policy for Lru {
+    fn init(&mut self, sets: usize, _log_size: usize) {
+        self.order = vec![Self::INITIAL; sets].into_boxed_slice();
+    }
+
+    #[inline]
+    fn on_hit(&mut self, set: usize, slot: usize, _pos: usize) {
+        self.order[set] = Self::touch(self.order[set], slot);
+    }
+
+    #[inline]
+    fn on_insert(&mut self, set: usize, slot: usize, _pos: usize) {
+        self.order[set] = Self::touch(self.order[set], slot);
+    }
+
+    #[inline]
+    fn choose_victim(&mut self, set: usize) -> usize {
+        (self.order[set] >> 60) as usize
+    }
+}
+
+/// Gives the slots of a full set a second chance, CLOCK style.
+///
+/// Each set keeps a 16-bit reference mask, one bit per slot, set when a
+/// lookup hits the slot and cleared when a new entry is written to it. To
+/// pick a victim, a per-set hand sweeps the slots: a referenced slot loses its
+/// bit and is skipped, and the first unreferenced slot is evicted. Hot
+/// entries therefore survive cold ones that share their set, at the cost of
+/// two bytes and a hand per set.
+#[derive(Debug, Clone, Default)]
+pub struct Clock {
+    referenced: Box<[u16]>,
+    hand: Box<[u8]>,
+}
+
+impl Policy for Clock {
+    fn init(&mut self, sets: usize, _log_size: usize) {
+        self.referenced = vec![0; sets].into_boxed_slice();
+        self.hand = vec![0; sets].into_boxed_slice();
+    }
+
+    #[inline]
+    fn on_hit(&mut self, set: usize, slot: usize, _pos: usize) {
+        self.referenced[set] |= 1 << slot;
+    }
+
+    #[inline]
+    fn on_insert(&mut self, set: usize, slot: usize, _pos: usize) {
+        self.referenced[set] &= !(1 << slot);
+    }
+
+    /// Sweeps in one step: the mask is rotated so the hand sits at bit 0, and
+    /// the run of referenced slots in front of the hand is cleared and skipped.
+    #[inline]
+    fn choose_victim(&mut self, set: usize) -> usize {
+        let hand = self.hand[set] as u32;
+        let skip = self.referenced[set].rotate_right(hand).trailing_ones();
+        let victim = if skip == SIMD_SIZE as u32 {
+            self.referenced[set] = 0;
+            hand
+        } else {
+            let swept = ((1u32 << skip) - 1) as u16;
+            self.referenced[set] &= !swept.rotate_left(hand);
+            (hand + skip) % SIMD_SIZE as u32
+        };
+        self.hand[set] = ((victim + 1) % SIMD_SIZE as u32) as u8;
+        victim as usize
+    }
+}
+
+/// S3-FIFO: a small probationary FIFO, a main FIFO and a ghost FIFO.
+///
+/// The log is split into a small queue, a tenth of the log, and a main queue
+/// holding the rest. Every log entry has a 2-bit access counter, bumped on
+/// hits. New keys enter the small queue, unless their hash is in the ghost
+/// queue, in which case they were evicted recently and go straight to main.
+///
+/// When the small queue wraps onto an entry that was read since it was
+/// written, the entry moves to the main queue; otherwise it is evicted and
+/// its hash joins the ghost queue, which remembers as many hashes as the main
+/// queue has entries. When the main queue wraps onto an entry that was read,
+/// the entry is kept with its counter decremented; otherwise it is evicted.
+/// One-hit wonders thus leave through the small queue without displacing
+/// the working set. Full sets evict round-robin, as with `Fifo`.
+#[derive(Debug, Clone, Default)]
+pub struct S3Fifo {
+    small: usize,
+    freq: Box<[u8]>,
+    ghost: VecDeque<u64>,
+    ghost_len: usize,
+    ghost_count: HashMap<u64, u32>,
+    sets: Fifo,
+}
+
+impl S3Fifo {
+    /// The queue new keys enter, and the small queue of the log.
+    const SMALL: usize = 0;
+    /// The queue of keys that were read while in the small queue.
+    const MAIN: usize = 1;
+    /// The most accesses an entry's counter remembers.
+    const MAX_FREQ: u8 = 3;
+
+    /// Remembers the hash of a key evicted from the small queue, forgetting
+    /// the oldest one if the ghost queue is full.
+    fn push_ghost(&mut self, hash: u64) {
+        self.ghost.push_back(hash);
+        *self.ghost_count.entry(hash).or_default() += 1;
+        if self.ghost.len() > self.ghost_len {
+            let oldest = self
+                .ghost
+                .pop_front()
+                .expect("the ghost queue is not empty");
+            if let Some(count) = self.ghost_count.get_mut(&oldest) {
+                *count -= 1;
+                if *count == 0 {
+                    self.ghost_count.remove(&oldest);
+                }
+            }
+        }
+    }
+}
+
+impl Policy for S3Fifo {
+    /// Logs of fewer than two entries can not be split and stay one queue.
+    fn init(&mut self, sets: usize, log_size: usize) {
+        self.small = if log_size >= 2 {
+            (log_size / 10).max(1)
+        } else {
+            0
+        };
+        self.freq = vec![0; log_size].into_boxed_slice();
+        self.ghost_len = log_size - self.small;
+        self.ghost.clear();
+        self.ghost_count.clear();
+        self.sets.init(sets, log_size);
+    }
+
+    #[inline]
+    fn on_hit(&mut self, _set: usize, _slot: usize, pos: usize) {
+        self.freq[pos] = (self.freq[pos] + 1).min(Self::MAX_FREQ);
+    }
+
+    #[inline]
+    fn on_insert(&mut self, _set: usize, _slot: usize, pos: usize) {
+        self.freq[pos] = 0;
+    }
+
+    #[inline]
+    fn choose_victim(&mut self, set: usize) -> usize {
+        self.sets.choose_victim(set)
+    }
+
+    fn log_queues(&self, log_size: usize) -> Vec<usize> {
+        if self.small == 0 {
+            vec![log_size]
+        } else {
+            vec![self.small, log_size - self.small]
+        }
+    }
+
+    fn queue_for(&mut self, hash: u64) -> usize {
+        if self.small != 0 && self.ghost_count.contains_key(&hash) {
+            Self::MAIN
+        } else {
+            Self::SMALL
+        }
+    }
+
+    fn reclaim(&mut self, queue: usize, pos: usize, hash: u64) -> Reclaim {
+        if self.small == 0 {
+            return Reclaim::Evict;
+        }
+        match (queue, self.freq[pos]) {
+            (Self::SMALL, 0) => {
+                self.push_ghost(hash);
+                Reclaim::Evict
+            }
+            (Self::SMALL, _) => {
+                self.freq[pos] = 0;
+                Reclaim::Requeue(Self::MAIN)
+            }
+            (_, 0) => Reclaim::Evict,
+            (_, _) => {
+                self.freq[pos] -= 1;
+                Reclaim::Requeue(Self::MAIN)
+            }
+        }
+    }
+
+    fn on_move(&mut self, from: usize, to: usize) {
+        self.freq[to] = std::mem::take(&mut self.freq[from]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fifo_round_robin() {
+        let mut fifo = Fifo::default();
+        fifo.init(2, 32);
+
+        let victims: Vec<_> = (0..18).map(|_| fifo.choose_victim(1)).collect();
+        assert_eq!(victims[..16], (0..16).collect::<Vec<_>>());
+        assert_eq!(victims[16..], [0, 1]);
+        assert_eq!(fifo.choose_victim(0), 0);
+    }
+
+    #[test]
+    fn lru_order() {
+        let mut lru = Lru::default();
+        lru.init(1, 32);
+        for slot in 0..SIMD_SIZE {
+            lru.on_insert(0, slot, slot);
+        }
+        assert_eq!(lru.choose_victim(0), 0);
+
+        lru.on_hit(0, 0, 0);
+        lru.on_hit(0, 15, 15);
+        lru.on_hit(0, 1, 1);
+        assert_eq!(lru.choose_victim(0), 2);
+
+        for slot in 2..15 {
+            lru.on_hit(0, slot, slot);
+        }
+        assert_eq!(lru.choose_victim(0), 0);
+        lru.on_insert(0, 0, 0);
+        assert_eq!(lru.choose_victim(0), 15);
+    }
+
+    #[test]
+    fn clock_second_chance() {
+        let mut clock = Clock::default();
+        clock.init(1, 32);
+        for slot in 0..SIMD_SIZE {
+            clock.on_insert(0, slot, slot);
+        }
+        assert_eq!(clock.choose_victim(0), 0);
+        clock.on_insert(0, 0, 0);
+
+        clock.on_hit(0, 1, 1);
+        clock.on_hit(0, 2, 2);
+        clock.on_hit(0, 4, 4);
+        assert_eq!(clock.choose_victim(0), 3);
+        clock.on_insert(0, 3, 3);
+        assert_eq!(clock.choose_victim(0), 5);
+        clock.on_insert(0, 5, 5);
+
+        for slot in 6..SIMD_SIZE {
+            clock.on_hit(0, slot, slot);
+        }
+        clock.on_hit(0, 0, 0);
+        assert_eq!(clock.choose_victim(0), 1);
+    }
+
+    #[test]
+    fn s3fifo_queues() {
+        let mut s3 = S3Fifo::default();
+        s3.init(4, 64);
+        assert_eq!(s3.log_queues(64), vec![6, 58]);
+        assert_eq!(s3.queue_for(7), S3Fifo::SMALL);
+
+        s3.on_insert(0, 0, 1);
+        assert_eq!(s3.reclaim(S3Fifo::SMALL, 1, 7), Reclaim::Evict);
+        assert_eq!(s3.queue_for(7), S3Fifo::MAIN);
+
+        s3.on_insert(0, 1, 2);
+        s3.on_hit(0, 1, 2);
+        assert_eq!(
+            s3.reclaim(S3Fifo::SMALL, 2, 8),
+            Reclaim::Requeue(S3Fifo::MAIN)
+        );
+        s3.on_move(2, 10);
+        assert_eq!(s3.reclaim(S3Fifo::MAIN, 10, 8), Reclaim::Evict);
+
+        for _ in 0..5 {
+            s3.on_hit(0, 2, 11);
+        }
+        for _ in 0..3 {
+            assert_eq!(
+                s3.reclaim(S3Fifo::MAIN, 11, 9),
+                Reclaim::Requeue(S3Fifo::MAIN)
+            );
+        }
+        assert_eq!(s3.reclaim(S3Fifo::MAIN, 11, 9), Reclaim::Evict);
+    }
+
+    #[test]
+    fn s3fifo_ghost_forgets() {
+        let mut s3 = S3Fifo::default();
+        s3.init(1, 2);
+        assert_eq!(s3.log_queues(2), vec![1, 1]);
+
+        s3.reclaim(S3Fifo::SMALL, 0, 7);
+        s3.reclaim(S3Fifo::SMALL, 0, 8);
+        assert_eq!(s3.queue_for(7), S3Fifo::SMALL);
+        assert_eq!(s3.queue_for(8), S3Fifo::MAIN);
+    }
+
+    #[test]
+    fn clock_all_referenced() {
+        let mut clock = Clock::default();
+        clock.init(1, 32);
+        for slot in 0..SIMD_SIZE {
+            clock.on_insert(0, slot, slot);
+            clock.on_hit(0, slot, slot);
+        }
+        assert_eq!(clock.choose_victim(0), 0);
+        clock.on_insert(0, 0, 0);
+        assert_eq!(clock.choose_victim(0), 1);
+    }
+}
+
+/* policy.rs ends here */
@@ -22,6 +22,10 @@
 use std::simd::{cmp::SimdPartialEq, u8x16};
 pub(crate) const SIMD_SIZE: usize = 16;
 
+/// The fingerprint held by slots that were never written. Keys never hash to
+/// it, so probing can not match an empty slot.
+pub(crate) const EMPTY_FINGER: u8 = 0;
+
 #[repr(C)]
 /// The `Set` struct is used to manage a collection of cache entries.
 /// It utilizes SIMD (Single Instruction, Multiple Data) operations to store
@@ -32,24 +36,25 @@ pub(crate) const SIMD_SIZE: usize = 16;
 /// - `valid_mask`: A 16-bit mask that indicates the validity of the corresponding
 ///   slots in the `fingers` register.
 /// - `_padding`: A padding field for alignment.
-/// - `next`: An index used for round-robin selection when all slots are filled.
+/// - `generation`: The cache generation the slots belong to. A set left
+///   behind by `flush_all` is treated as empty and reset on its next insert.
 /// - `pointers`: An array of pointers to the actual cache entries.
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct Set {
     pub(crate) fingers: u8x16,
     pub(crate) valid_mask: u16,
     pub(crate) _padding: u16,
-    pub(crate) next: usize,
+    pub(crate) generation: u32,
     pub(crate) pointers: [usize; SIMD_SIZE],
 }
 
 impl Default for Set {
     fn default() -> Self {
         Self {
-            fingers: u8x16::splat(0),
+            fingers: u8x16::splat(EMPTY_FINGER),
             valid_mask: 0,
-            next: 0,
             _padding: 0,
+            generation: 0,
             pointers: [0; SIMD_SIZE],
         }
     }
@@ -68,22 +73,26 @@ const LANE_MASKS: [u8x16; SIMD_SIZE] = {
 };
 
 impl Set {
-    /// Finds the next available slot in the cache.
+    /// Finds the first free slot in the set.
     ///
-    /// This function first attempts to find the first empty slot by looking for
-    /// a zero in the `valid_mask`. If no empty slots are found, it resorts to
-    /// round-robin selection using the `next` index.
+    /// This function looks for the first zero in the `valid_mask`. If every
+    /// slot is taken it returns `None`, and the table's `Policy` picks a victim.
     #[inline(always)]
-    pub fn next_slot(&mut self) -> usize {
-        if self.valid_mask != u16::MAX {
-            let inv_mask = !self.valid_mask;
-            let first_zero = inv_mask.trailing_zeros() as usize;
-            return first_zero;
+    pub fn free_slot(&self) -> Option<usize> {
+        if self.valid_mask == u16::MAX {
+            return None;
         }
+        Some((!self.valid_mask).trailing_zeros() as usize)
+    }
 
-        let slot = self.next;
-        self.next = (self.next + 1) % SIMD_SIZE;
-        slot
+    /// Empties the set and moves it to the given generation.
+    ///
+    /// Only the mask is reset; stale fingers and pointers are left in place
+    /// since no valid slot refers to them.
+    #[inline(always)]
+    pub fn reset(&mut self, generation: u32) {
+        self.valid_mask = 0;
+        self.generation = generation;
     }
 
     /// Sets the finger value at a specified slot in the `fingers` register.
@@ -103,22 +112,86 @@ impl Set {
 
     /// Probes the `fingers` register for a given needle value.
     ///
-    /// This function checks if the given 8-bit needle value exists in the
-    /// `fingers` register. Returns the index of the slot if found, or `None`
-    /// if the needle is not present.
+    /// This function compares the given 8-bit needle value against every slot
+    /// of the `fingers` register and returns an iterator over the valid slots
+    /// that hold it, in ascending slot order. A match only means the fingerprint
+    /// is equal; the caller must still compare the full key.
     ///
     /// # Arguments
     /// * `needle` - The 8-bit value to search for in the `fingers` register.
     #[inline(always)]
-    pub fn probe(&self, needle: u8) -> Option<usize> {
+    pub fn probe(&self, needle: u8) -> Matches {
         let simd_needle = u8x16::splat(needle);
         let cmp_mask = self.fingers.simd_eq(simd_needle).to_bitmask();
-        let live = cmp_mask as u16 & self.valid_mask;
-        if live != 0 {
-            let slot = live.trailing_zeros() as usize;
-            return Some(slot);
+        Matches(cmp_mask as u16 & self.valid_mask)
+    }
+}
+
+/// An iterator over the slots of a `Set` whose fingerprint matched a probe.
+///
+/// It walks the bitmask of live matches from the lowest slot upwards,
+/// clearing one bit per step.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Matches(u16);
+
+impl Iterator for Matches {
+    type Item = usize;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<usize> {
+        if self.0 == 0 {
+            return None;
+        }
+        let slot = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(slot)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let count = self.0.count_ones() as usize;
+        (count, Some(count))
+    }
+}
+
+impl ExactSizeIterator for Matches {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fills the given slots with `finger` and marks them valid.
+    fn fill(set: &mut Set, slots: &[usize], finger: u8) {
+        for &slot in slots {
+            set.set_finger(slot, finger);
+            set.valid_mask |= 1 << slot;
         }
-        None
+    }
+
+    #[test]
+    fn probe_empty() {
+        let set = Set::default();
+        assert_eq!(set.probe(0).count(), 0);
+    }
+
+    #[test]
+    fn probe_yields_every_match() {
+        let mut set = Set::default();
+        fill(&mut set, &[1, 4, 15], 7);
+        fill(&mut set, &[2, 3], 9);
+
+        assert_eq!(set.probe(7).collect::<Vec<_>>(), vec![1, 4, 15]);
+        assert_eq!(set.probe(9).collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(set.probe(7).len(), 3);
+    }
+
+    #[test]
+    fn probe_skips_invalid_slots() {
+        let mut set = Set::default();
+        fill(&mut set, &[0, 5, 6], 7);
+        set.valid_mask &= !(1 << 5);
+
+        assert_eq!(set.probe(7).collect::<Vec<_>>(), vec![0, 6]);
     }
 }
 /* set.rs ends here */
@@ -19,8 +19,14 @@
 * along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use crate::CacheTable;
-use std::hash::Hash;
+use crate::set::SIMD_SIZE;
+use crate::{
+    CacheTable, Entry, Fifo, Insertion, Occupancy, Policy, RemovalCause, TimeSource, TinyLfu,
+    Weigher, WyHashBuilder,
+};
+use std::borrow::Borrow;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
 use std::{
     cell::UnsafeCell,
     sync::atomic::{AtomicUsize, Ordering},
@@ -36,25 +42,42 @@ use std::{
 /// - `VALUE`: The type of the values stored in the cache.
 /// - `LOG_SIZE`: The size of the log in the cache, must be a power of two.
 /// - `SET_SIZE`: The number of sets in the cache, must be a power of two.
+/// - `HASHER`: The `BuildHasher` that places keys in sets, `WyHashBuilder` by default.
+/// - `POLICY`: The eviction `Policy` of full sets, `Fifo` by default.
 ///
 /// The `Shard` uses an `UnsafeCell` to wrap the `CacheTable` and an `AtomicUsize`
 /// to track the thread that is associated with the shard for safe concurrent access.
-pub struct Shard<KEY, VALUE, const LOG_SIZE: usize, const SET_SIZE: usize> {
-    data: UnsafeCell<CacheTable<KEY, VALUE, LOG_SIZE, SET_SIZE>>,
+pub struct Shard<
+    KEY,
+    VALUE,
+    const LOG_SIZE: usize,
+    const SET_SIZE: usize,
+    HASHER = WyHashBuilder,
+    POLICY = Fifo,
+> {
+    data: UnsafeCell<CacheTable<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY>>,
     registered_thread: AtomicUsize,
 }
 
-unsafe impl<KEY: Send, VALUE: Send, const LOG_SIZE: usize, const SET_SIZE: usize> Sync
-    for Shard<KEY, VALUE, LOG_SIZE, SET_SIZE>
+unsafe impl<
+        KEY: Send,
+        VALUE: Send,
+        const LOG_SIZE: usize,
+        const SET_SIZE: usize,
+        HASHER: Sync,
+        POLICY: Send,
+    > Sync for Shard<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY>
 {
 }
 
 impl<
-        KEY: Default + Hash + Eq + PartialEq + Clone,
-        VALUE: Default + Clone,
+        KEY: Hash + Eq,
+        VALUE,
         const LOG_SIZE: usize,
         const SET_SIZE: usize,
-    > Shard<KEY, VALUE, LOG_SIZE, SET_SIZE>
+        HASHER: BuildHasher + Default,
+        POLICY: Policy + Default,
+    > Shard<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY>
 {
     /// Creates a new `Shard` instance.
     ///
@@ -62,8 +85,46 @@ impl<
     /// `registered_thread` to `usize::MAX`, indicating that the shard is not yet
     /// associated with any thread.
     pub fn new() -> Self {
+        Self::with_hasher_and_policy(HASHER::default(), POLICY::default())
+    }
+}
+
+impl<
+        KEY: Hash + Eq,
+        VALUE,
+        const LOG_SIZE: usize,
+        const SET_SIZE: usize,
+        HASHER: BuildHasher,
+        POLICY: Policy + Default,
+    > Shard<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY>
+{
+    /// Creates a new `Shard` instance whose `CacheTable` hashes keys with `hasher`.
+    ///
+    /// # Arguments
+    /// * `hasher` - The `BuildHasher` used to place keys in sets.
+    pub fn with_hasher(hasher: HASHER) -> Self {
+        Self::with_hasher_and_policy(hasher, POLICY::default())
+    }
+}
+
+impl<
+        KEY: Hash + Eq,
+        VALUE,
+        const LOG_SIZE: usize,
+        const SET_SIZE: usize,
+        HASHER: BuildHasher,
+        POLICY: Policy,
+    > Shard<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY>
+{
+    /// Creates a new `Shard` instance whose `CacheTable` hashes keys with
+    /// `hasher` and evicts from full sets with `policy`.
+    ///
+    /// # Arguments
+    /// * `hasher` - The `BuildHasher` used to place keys in sets.
+    /// * `policy` - The `Policy` that picks the victim of a full set.
+    pub fn with_hasher_and_policy(hasher: HASHER, policy: POLICY) -> Self {
         Self {
-            data: UnsafeCell::new(CacheTable::<KEY, VALUE, LOG_SIZE, SET_SIZE>::new()),
+            data: UnsafeCell::new(CacheTable::with_hasher_and_policy(hasher, policy)),
             registered_thread: AtomicUsize::new(usize::MAX),
         }
     }
@@ -81,6 +142,19 @@ impl<
             .is_ok()
     }
 
+    /// Returns the `CacheTable` of the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard, which is what makes handing out the table sound.
+    #[inline]
+    fn table(&self) -> &CacheTable<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY> {
+        assert_eq!(
+            self.registered_thread.load(Ordering::Relaxed),
+            thread::current().id().as_u64().get() as usize
+        );
+        unsafe { &*self.data.get() }
+    }
+
     /// Inserts a key-value pair into the `CacheTable` within the `Shard`.
     ///
     /// This function asserts that the current thread is the registered thread
@@ -89,15 +163,73 @@ impl<
     /// # Arguments
     /// * `key` - The key to insert.
     /// * `value` - The value to associate with the key.
-    pub fn insert(&self, key: KEY, value: VALUE)
+    ///
+    /// # Returns
+    /// An `Insertion` describing the previous value, any evicted entries or
+    /// the pair rejected by the admission filter.
+    pub fn insert(&self, key: KEY, value: VALUE) -> Insertion<KEY, VALUE> {
+        self.table().insert(key, value)
+    }
+
+    /// Inserts a key-value pair that expires once `ttl` has passed into the
+    /// `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    ///
+    /// # Arguments
+    /// * `key` - The key to insert.
+    /// * `value` - The value to associate with the key.
+    /// * `ttl` - How long the entry lives, as measured by the time source.
+    pub fn insert_with_ttl(&self, key: KEY, value: VALUE, ttl: Duration) -> Insertion<KEY, VALUE> {
+        self.table().insert_with_ttl(key, value, ttl)
+    }
+
+    /// Hashes a key the way the `CacheTable` within the `Shard` does.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard; `ShardedTable::hash_key` hashes from any thread.
+    ///
+    /// # Arguments
+    /// * `key` - The key to hash.
+    pub fn hash_key<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.table().hash_key(key)
+    }
+
+    /// Inserts a key-value pair whose hash the caller already computed into
+    /// the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard. Every operation on a key must be given the same hash.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of `key`.
+    /// * `key` - The key to insert.
+    /// * `value` - The value to associate with the key.
+    ///
+    /// # Returns
+    /// An `Insertion` describing the previous value, any evicted entries or
+    /// the pair rejected by the admission filter.
+    pub fn insert_hashed(&self, hash: u64, key: KEY, value: VALUE) -> Insertion<KEY, VALUE> {
+        self.table().insert_hashed(hash, key, value)
+    }
+
+    /// Inserts a batch of key-value pairs into the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard and then hashes and prefetches the whole batch before
+    /// inserting the pairs in order.
+    ///
+    /// # Arguments
+    /// * `items` - The key-value pairs to insert.
+    ///
+    /// # Returns
+    /// The `Insertion` of every pair, in order.
+    pub fn insert_many<I>(&self, items: I) -> Vec<Insertion<KEY, VALUE>>
     where
-        KEY: Eq + std::hash::Hash,
+        I: IntoIterator<Item = (KEY, VALUE)>,
     {
-        assert_eq!(
-            self.registered_thread.load(Ordering::Relaxed),
-            thread::current().id().as_u64().get() as usize
-        );
-        unsafe { &mut *self.data.get() }.insert(key, value);
+        self.table().insert_many(items)
     }
 
     /// Retrieves a value from the `CacheTable` within the `Shard` for a given key.
@@ -110,24 +242,341 @@ impl<
     ///
     /// # Returns
     /// An `Option` containing the value if the key exists, `None` otherwise.
-    pub fn get(&self, key: &KEY) -> Option<VALUE>
+    pub fn get<Q>(&self, key: &Q) -> Option<VALUE>
     where
-        KEY: Eq + std::hash::Hash,
+        KEY: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
         VALUE: Clone,
     {
-        assert_eq!(
-            self.registered_thread.load(Ordering::Relaxed),
-            thread::current().id().as_u64().get() as usize
-        );
-        unsafe { &*self.data.get() }.get(key)
+        self.table().get(key)
+    }
+
+    /// Retrieves a value for a key whose hash the caller already computed from
+    /// the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of `key`.
+    /// * `key` - A reference to the key for which to retrieve the value.
+    ///
+    /// # Returns
+    /// An `Option` containing the value if the key exists, `None` otherwise.
+    pub fn get_hashed<Q>(&self, hash: u64, key: &Q) -> Option<VALUE>
+    where
+        KEY: Borrow<Q>,
+        Q: Eq + ?Sized,
+        VALUE: Clone,
+    {
+        self.table().get_hashed(hash, key)
+    }
+
+    /// Retrieves the values for a batch of keys from the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard and then hashes and prefetches the whole batch before
+    /// resolving the lookups.
+    ///
+    /// # Arguments
+    /// * `keys` - The keys for which to retrieve the values.
+    ///
+    /// # Returns
+    /// The value of every key, in order, or `None` for the keys not cached.
+    pub fn get_many<Q>(&self, keys: &[Q]) -> Vec<Option<VALUE>>
+    where
+        KEY: Borrow<Q>,
+        Q: Hash + Eq,
+        VALUE: Clone,
+    {
+        self.table().get_many(keys)
+    }
+
+    /// Calls `f` with a reference to the value cached for a given key in the
+    /// `CacheTable` within the `Shard`, without cloning it.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard and then applies `f` to the value associated with the key.
+    ///
+    /// # Arguments
+    /// * `key` - A reference to the key for which to read the value.
+    /// * `f` - The closure applied to the cached value.
+    ///
+    /// # Returns
+    /// An `Option` containing the result of `f` if the key exists, `None` otherwise.
+    pub fn get_with<Q, R, F>(&self, key: &Q, f: F) -> Option<R>
+    where
+        KEY: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        F: FnOnce(&VALUE) -> R,
+    {
+        self.table().get_with(key, f)
+    }
+
+    /// Checks whether a key is cached in the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard and then looks the key up without touching its value.
+    ///
+    /// # Arguments
+    /// * `key` - A reference to the key to look up.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        KEY: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.table().contains_key(key)
+    }
+
+    /// Gets the entry for a key in the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard and then probes the key once, returning a view that can
+    /// read, update, or fill the entry.
+    ///
+    /// # Arguments
+    /// * `key` - The key to look up.
+    pub fn entry(&self, key: KEY) -> Entry<'_, KEY, VALUE, POLICY> {
+        self.table().entry(key)
+    }
+
+    /// Returns the number of live entries in the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn len(&self) -> usize {
+        self.table().len()
+    }
+
+    /// Returns `true` if the `CacheTable` within the `Shard` holds no live entries.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn is_empty(&self) -> bool {
+        self.table().is_empty()
+    }
+
+    /// Returns the number of entries the `CacheTable` within the `Shard` can hold at once.
+    pub fn capacity(&self) -> usize {
+        LOG_SIZE.min(SET_SIZE * SIMD_SIZE)
+    }
+
+    /// Reports how full the sets and the log of the `CacheTable` within the `Shard` are.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn occupancy(&self) -> Occupancy {
+        self.table().occupancy()
+    }
+
+    /// Registers a removal listener on the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard. The listener then fires on that thread with the key,
+    /// value and cause of every entry that leaves this shard.
+    ///
+    /// # Arguments
+    /// * `listener` - The callback to fire on every removal.
+    pub fn set_listener<F>(&self, listener: F)
+    where
+        F: FnMut(&KEY, &VALUE, RemovalCause) + Send + 'static,
+    {
+        self.table().set_listener(listener);
+    }
+
+    /// Unregisters the removal listener of the `CacheTable` within the
+    /// `Shard`, if any.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn clear_listener(&self) {
+        self.table().clear_listener();
+    }
+
+    /// Installs a TinyLFU admission filter on the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard. Each shard keeps its own filter, fed by the keys it sees.
+    ///
+    /// # Arguments
+    /// * `admission` - The filter, usually sized with `TinyLfu::new(capacity)`.
+    pub fn set_admission(&self, admission: TinyLfu) {
+        self.table().set_admission(admission);
+    }
+
+    /// Removes the admission filter of the `CacheTable` within the `Shard`,
+    /// if any, so every insert is admitted.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn clear_admission(&self) {
+        self.table().clear_admission();
+    }
+
+    /// Bounds the total weight of the live entries of the `CacheTable` within
+    /// the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard. Each shard keeps its own budget.
+    ///
+    /// # Arguments
+    /// * `weigher` - Measures the weight of each entry, e.g. in bytes.
+    /// * `max_weight` - The total weight the live entries may add up to.
+    pub fn set_weigher<W>(&self, weigher: W, max_weight: u64)
+    where
+        W: Weigher<KEY, VALUE> + Send + 'static,
+    {
+        self.table().set_weigher(weigher, max_weight);
+    }
+
+    /// Removes the weigher and weight budget of the `CacheTable` within the
+    /// `Shard`, if any.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn clear_weigher(&self) {
+        self.table().clear_weigher();
+    }
+
+    /// Returns the total weight of the live entries of the `CacheTable`
+    /// within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn weight(&self) -> u64 {
+        self.table().weight()
+    }
+
+    /// Gives entries inserted into the `CacheTable` within the `Shard` without
+    /// a time-to-live this one.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    ///
+    /// # Arguments
+    /// * `ttl` - How long new entries live, as measured by the time source.
+    pub fn set_default_ttl(&self, ttl: Duration) {
+        self.table().set_default_ttl(ttl);
+    }
+
+    /// Removes the default time-to-live of the `CacheTable` within the
+    /// `Shard`, so entries inserted without one never expire.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn clear_default_ttl(&self) {
+        self.table().clear_default_ttl();
+    }
+
+    /// Replaces the time source of the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    ///
+    /// # Arguments
+    /// * `time` - The new time source.
+    pub fn set_time_source<T>(&self, time: T)
+    where
+        T: TimeSource + Send + 'static,
+    {
+        self.table().set_time_source(time);
+    }
+
+    /// Removes every expired entry from the `CacheTable` within the `Shard`
+    /// and returns how many there were.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn purge_expired(&self) -> usize {
+        self.table().purge_expired()
+    }
+
+    /// Attaches a timing wheel to the `CacheTable` within the `Shard`.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard. Each shard keeps its own wheel, advanced by `tick`.
+    ///
+    /// # Arguments
+    /// * `resolution` - The length of a tick of the wheel.
+    pub fn enable_timing_wheel(&self, resolution: Duration) {
+        self.table().enable_timing_wheel(resolution);
+    }
+
+    /// Detaches the timing wheel of the `CacheTable` within the `Shard`, if
+    /// any.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    pub fn disable_timing_wheel(&self) {
+        self.table().disable_timing_wheel();
+    }
+
+    /// Advances the timing wheel of the `CacheTable` within the `Shard` to
+    /// `now` and removes every entry whose deadline has passed.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard, so the listener fires on the owner thread.
+    ///
+    /// # Arguments
+    /// * `now` - The current time, as read from the shard's time source.
+    ///
+    /// # Returns
+    /// The number of entries removed.
+    pub fn tick(&self, now: Instant) -> usize {
+        self.table().tick(now)
+    }
+
+    /// Removes a key from the `CacheTable` within the `Shard` and returns its value.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard and then removes the entry associated with the key.
+    ///
+    /// # Arguments
+    /// * `key` - A reference to the key to remove.
+    ///
+    /// # Returns
+    /// An `Option` containing the removed value if the key was cached, `None` otherwise.
+    pub fn remove<Q>(&self, key: &Q) -> Option<VALUE>
+    where
+        KEY: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.table().remove(key)
+    }
+
+    /// Removes a key whose hash the caller already computed from the
+    /// `CacheTable` within the `Shard` and returns its value.
+    ///
+    /// This function asserts that the current thread is the registered thread
+    /// for the shard.
+    ///
+    /// # Arguments
+    /// * `hash` - The hash of `key`.
+    /// * `key` - A reference to the key to remove.
+    ///
+    /// # Returns
+    /// An `Option` containing the removed value if the key was cached, `None` otherwise.
+    pub fn remove_hashed<Q>(&self, hash: u64, key: &Q) -> Option<VALUE>
+    where
+        KEY: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        self.table().remove_hashed(hash, key)
     }
 }
 
-impl<KEY: Default + Hash + Eq + PartialEq + Clone, VALUE: Default + Clone, const LOG_SIZE: usize, const SET_SIZE: usize> Default for Shard<KEY, VALUE, LOG_SIZE, SET_SIZE> {
+impl<
+        KEY: Hash + Eq,
+        VALUE,
+        const LOG_SIZE: usize,
+        const SET_SIZE: usize,
+        HASHER: BuildHasher + Default,
+        POLICY: Policy + Default,
+    > Default for Shard<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY>
+{
     fn default() -> Self {
         Self::new()
     }
 }
 
-
 /* shardedtable.rs ends here */
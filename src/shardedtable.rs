@@ -20,7 +20,8 @@
 */
 
 use crate::shard::Shard;
-use std::hash::Hash;
+use crate::{Fifo, Policy, WyHashBuilder};
+use std::hash::{BuildHasher, Hash};
 
 /// The `ShardedTable` struct is responsible for managing a collection of shards,
 /// each of which represents a partition of the cache. It provides methods to
@@ -31,19 +32,31 @@ use std::hash::Hash;
 /// - `VALUE`: The type of the values stored in the cache.
 /// - `LOG_SIZE`: The size of the log in each shard, must be a power of two.
 /// - `SET_SIZE`: The number of sets in each shard, must be a power of two.
+/// - `HASHER`: The `BuildHasher` shared by all shards, `WyHashBuilder` by default.
+/// - `POLICY`: The eviction `Policy` of every shard, `Fifo` by default.
 ///
 /// The `ShardedTable` simplifies the management of multiple shards and provides
 /// a convenient interface for interacting with them.
-pub struct ShardedTable<KEY, VALUE, const LOG_SIZE: usize, const SET_SIZE: usize> {
-    shards: Vec<Shard<KEY, VALUE, LOG_SIZE, SET_SIZE>>,
+pub struct ShardedTable<
+    KEY,
+    VALUE,
+    const LOG_SIZE: usize,
+    const SET_SIZE: usize,
+    HASHER = WyHashBuilder,
+    POLICY = Fifo,
+> {
+    shards: Vec<Shard<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY>>,
+    hasher: HASHER,
 }
 
 impl<
-        KEY: Default + Hash + Eq + PartialEq + Clone,
-        VALUE: Default + Clone,
+        KEY: Hash + Eq,
+        VALUE,
         const LOG_SIZE: usize,
         const SET_SIZE: usize,
-    > ShardedTable<KEY, VALUE, LOG_SIZE, SET_SIZE>
+        HASHER: BuildHasher + Default + Clone,
+        POLICY: Policy + Default + Clone,
+    > ShardedTable<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY>
 {
     /// Creates a new `ShardedTable` instance.
     ///
@@ -52,6 +65,74 @@ impl<
     pub fn new() -> Self {
         Self::default()
     }
+}
+
+impl<
+        KEY: Hash + Eq,
+        VALUE,
+        const LOG_SIZE: usize,
+        const SET_SIZE: usize,
+        POLICY: Policy + Default + Clone,
+    > ShardedTable<KEY, VALUE, LOG_SIZE, SET_SIZE, WyHashBuilder, POLICY>
+{
+    /// Creates a new `ShardedTable` whose shards all hash keys from a fixed
+    /// seed instead of a random one.
+    ///
+    /// # Arguments
+    /// * `seed` - The seed of the WyHash hasher.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_hasher(WyHashBuilder::with_seed(seed))
+    }
+}
+
+impl<
+        KEY: Hash + Eq,
+        VALUE,
+        const LOG_SIZE: usize,
+        const SET_SIZE: usize,
+        HASHER: BuildHasher + Clone,
+        POLICY: Policy + Clone,
+    > ShardedTable<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY>
+{
+    /// Creates a new `ShardedTable` whose shards all hash keys with a clone of
+    /// `hasher`.
+    ///
+    /// # Arguments
+    /// * `hasher` - The `BuildHasher` used to place keys in sets.
+    pub fn with_hasher(hasher: HASHER) -> Self
+    where
+        POLICY: Default,
+    {
+        Self::with_hasher_and_policy(hasher, POLICY::default())
+    }
+
+    /// Creates a new `ShardedTable` whose shards all hash keys with a clone of
+    /// `hasher` and evict from full sets with their own clone of `policy`.
+    ///
+    /// # Arguments
+    /// * `hasher` - The `BuildHasher` used to place keys in sets.
+    /// * `policy` - The `Policy` that picks the victim of a full set.
+    pub fn with_hasher_and_policy(hasher: HASHER, policy: POLICY) -> Self {
+        Self {
+            shards: (0..SET_SIZE)
+                .map(|_| Shard::with_hasher_and_policy(hasher.clone(), policy.clone()))
+                .collect(),
+            hasher,
+        }
+    }
+
+    /// Hashes a key the way every shard does, from any thread.
+    ///
+    /// The hash can pick the shard a key belongs to and then be handed to the
+    /// `_hashed` methods of that shard, so the key is hashed only once. Pick
+    /// the shard from the low 32 bits, as in `hash as usize % shards`; the
+    /// top byte is the fingerprint within the shard.
+    ///
+    /// # Arguments
+    /// * `key` - The key to hash.
+    pub fn hash_key<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hasher.hash_one(key)
+    }
 
     /// Retrieves a reference to a specific shard within the table.
     ///
@@ -60,28 +141,29 @@ impl<
     ///
     /// # Returns
     /// A reference to the shard at the specified index.
-    pub fn get_shard(&self, shard_id: usize) -> &Shard<KEY, VALUE, LOG_SIZE, SET_SIZE> {
+    pub fn get_shard(
+        &self,
+        shard_id: usize,
+    ) -> &Shard<KEY, VALUE, LOG_SIZE, SET_SIZE, HASHER, POLICY> {
         &self.shards[shard_id]
     }
 }
 
-impl<
-        K: Default + Hash + Eq + PartialEq + Clone,
-        V: Default + Clone,
-        const L: usize,
-        const S: usize,
-    > Default for ShardedTable<K, V, L, S>
+impl<K: Hash + Eq, V, const L: usize, const S: usize, H, P> Default
+    for ShardedTable<K, V, L, S, H, P>
+where
+    H: BuildHasher + Default + Clone,
+    P: Policy + Default + Clone,
 {
     fn default() -> Self {
-        Self {
-            shards: (0..S).map(|_| Shard::new()).collect(),
-        }
+        Self::with_hasher(H::default())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use smallvec::smallvec;
 
     #[test]
     fn test_basic_insert_get_single_thread() {
@@ -96,6 +178,228 @@ mod tests {
         assert_eq!(shard.get(&30), None);
     }
 
+    #[test]
+    fn test_remove_in_shard() {
+        let table = ShardedTable::<u64, String, 4, 32>::new();
+        let shard = table.get_shard(0);
+        shard.register();
+        shard.insert(10, "ten".to_string());
+
+        assert_eq!(shard.remove(&10), Some("ten".to_string()));
+        assert_eq!(shard.get(&10), None);
+        assert_eq!(shard.remove(&10), None);
+    }
+
+    #[test]
+    fn test_borrowed_access_in_shard() {
+        let table = ShardedTable::<String, Vec<u8>, 4, 32>::new();
+        let shard = table.get_shard(0);
+        shard.register();
+        shard.insert("ten".to_string(), vec![10; 8]);
+
+        assert!(shard.contains_key("ten"));
+        assert_eq!(shard.get_with("ten", |value| value.len()), Some(8));
+        assert!(!shard.contains_key("eleven"));
+    }
+
+    #[test]
+    fn test_entry_in_shard() {
+        let table = ShardedTable::<u64, u64, 4, 32>::new();
+        let shard = table.get_shard(0);
+        shard.register();
+
+        *shard.entry(10).or_insert(0).unwrap() += 1;
+        *shard.entry(10).or_insert(0).unwrap() += 1;
+
+        assert_eq!(shard.get(&10), Some(2));
+    }
+
+    #[test]
+    fn test_listener_per_shard() {
+        use crate::RemovalCause;
+        use std::sync::{Arc, Mutex};
+
+        let table = ShardedTable::<u64, u64, 2, 32>::new();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let shard0 = table.get_shard(0);
+        shard0.register();
+        let sink = Arc::clone(&events);
+        shard0.set_listener(move |key, _, cause| sink.lock().unwrap().push((*key, cause)));
+
+        let shard1 = table.get_shard(1);
+        shard1.register();
+        for key in 0..3 {
+            shard0.insert(key, key);
+            shard1.insert(key, key);
+        }
+
+        assert_eq!(*events.lock().unwrap(), vec![(0, RemovalCause::LogWrap)]);
+
+        shard0.clear_listener();
+        shard0.insert(3, 3);
+        assert_eq!(events.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_admission_per_shard() {
+        use crate::{Insertion, TinyLfu};
+
+        let table = ShardedTable::<u64, u64, 2, 32>::new();
+        let shard0 = table.get_shard(0);
+        shard0.register();
+        shard0.set_admission(TinyLfu::new(64));
+        let shard1 = table.get_shard(1);
+        shard1.register();
+        for key in 0..2 {
+            shard0.insert(key, key);
+            shard1.insert(key, key);
+        }
+
+        assert_eq!(shard0.insert(2, 2), Insertion::Rejected(2, 2));
+        assert_eq!(shard1.insert(2, 2), Insertion::Evicted(smallvec![(0, 0)]));
+
+        shard0.clear_admission();
+        assert_eq!(shard0.insert(2, 2), Insertion::Evicted(smallvec![(0, 0)]));
+    }
+
+    #[test]
+    fn test_weight_per_shard() {
+        let table = ShardedTable::<u64, Vec<u8>, 2, 32>::new();
+        let shard = table.get_shard(0);
+        shard.register();
+        shard.set_weigher(|_: &u64, value: &Vec<u8>| value.len() as u32, 16);
+        shard.insert(0, vec![0; 10]);
+        shard.insert(1, vec![1; 10]);
+
+        assert_eq!(shard.weight(), 10);
+        assert_eq!(shard.get(&1), Some(vec![1; 10]));
+
+        shard.clear_weigher();
+        shard.insert(2, vec![2; 10]);
+        assert_eq!(shard.weight(), 0);
+        assert_eq!(shard.len(), 2);
+    }
+
+    #[test]
+    fn test_ttl_per_shard() {
+        use crate::ManualTime;
+        use std::time::Duration;
+
+        let table = ShardedTable::<u64, u64, 4, 32>::new();
+        let shard = table.get_shard(0);
+        shard.register();
+        let time = ManualTime::new();
+        shard.set_time_source(time.clone());
+        shard.set_default_ttl(Duration::from_secs(10));
+        shard.insert(0, 0);
+        shard.insert_with_ttl(1, 1, Duration::from_secs(1));
+        time.advance(Duration::from_secs(1));
+
+        assert_eq!(shard.purge_expired(), 1);
+        assert_eq!(shard.get(&0), Some(0));
+        assert_eq!(shard.get(&1), None);
+
+        shard.clear_default_ttl();
+        shard.insert(2, 2);
+        time.advance(Duration::from_secs(10));
+        assert_eq!(shard.purge_expired(), 1);
+        assert_eq!(shard.get(&2), Some(2));
+    }
+
+    #[test]
+    fn test_timing_wheel_per_shard() {
+        use crate::{ManualTime, RemovalCause, TimeSource};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let table = ShardedTable::<u64, u64, 4, 32>::new();
+        let shard = table.get_shard(0);
+        shard.register();
+        let time = ManualTime::new();
+        shard.set_time_source(time.clone());
+        shard.enable_timing_wheel(Duration::from_millis(1));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&events);
+        shard.set_listener(move |key, _, cause| sink.lock().unwrap().push((*key, cause)));
+        shard.insert_with_ttl(0, 0, Duration::from_millis(5));
+        shard.insert(1, 1);
+        time.advance(Duration::from_millis(5));
+
+        assert_eq!(shard.tick(time.now()), 1);
+        assert_eq!(*events.lock().unwrap(), vec![(0, RemovalCause::Expired)]);
+        assert_eq!(shard.len(), 1);
+
+        shard.disable_timing_wheel();
+        shard.insert_with_ttl(2, 2, Duration::from_millis(5));
+        time.advance(Duration::from_millis(5));
+        assert_eq!(shard.tick(time.now()), 0);
+        assert_eq!(shard.len(), 2);
+    }
+
+    #[test]
+    fn test_len_per_shard() {
+        let table = ShardedTable::<u64, u64, 4, 32>::new();
+        let shard = table.get_shard(0);
+        shard.register();
+        assert!(shard.is_empty());
+        assert_eq!(shard.capacity(), 4);
+
+        for key in 0..6 {
+            shard.insert(key, key);
+        }
+
+        assert_eq!(shard.len(), 4);
+        assert_eq!(shard.occupancy().live, 4);
+    }
+
+    #[test]
+    fn test_shared_hasher() {
+        let table = ShardedTable::<u64, u64, 4, 32>::with_seed(7);
+        let shard = table.get_shard(3);
+        shard.register();
+        shard.insert(10, 100);
+
+        assert_eq!(shard.get(&10), Some(100));
+    }
+
+    #[test]
+    fn test_batch_in_shard() {
+        let table = ShardedTable::<u64, u64, 8, 32>::new();
+        let shard = table.get_shard(0);
+        shard.register();
+
+        shard.insert_many((0..4).map(|key| (key, key + 1)));
+
+        assert_eq!(shard.get_many(&[0, 3, 4]), vec![Some(1), Some(4), None]);
+    }
+
+    #[test]
+    fn test_hashed_in_shard() {
+        let table = ShardedTable::<u64, u64, 64, 32>::new();
+        let hash = table.hash_key(&10u64);
+        let shard = table.get_shard(hash as usize % 32);
+        shard.register();
+        assert_eq!(shard.hash_key(&10u64), hash);
+
+        shard.insert_hashed(hash, 10, 100);
+        assert_eq!(shard.get(&10), Some(100));
+        assert_eq!(shard.get_hashed(hash, &10), Some(100));
+        assert_eq!(shard.remove_hashed(hash, &10), Some(100));
+        assert!(shard.is_empty());
+
+        // Keys routed to the shard by the low bits of their hash still spread
+        // over its sets, so a full log loses none of them to a full set.
+        let routed: Vec<_> = (0..)
+            .map(|key: u64| (table.hash_key(&key), key))
+            .filter(|&(key_hash, _)| key_hash as usize % 32 == hash as usize % 32)
+            .take(64)
+            .collect();
+        for &(key_hash, key) in &routed {
+            shard.insert_hashed(key_hash, key, key);
+        }
+        assert_eq!(shard.len(), 64);
+    }
+
     #[test]
     fn test_double_register() {
         let table = ShardedTable::<u32, u32, 2, 32>::new();
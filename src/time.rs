@@ -0,0 +1,133 @@
+/* time.rs --- TIME
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Tells a `CacheTable` what time it is, to stamp and expire entries with
+/// a time-to-live.
+pub trait TimeSource {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// Reads the monotonic system clock. This is the default time source.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MonotonicTime;
+
+impl TimeSource for MonotonicTime {
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A time source that only moves when told to, so expiration can be tested
+/// deterministically without sleeping.
+///
+/// Clones share the same time, so one clone can be handed to a table and
+/// another kept to advance it.
+#[derive(Debug, Clone)]
+pub struct ManualTime {
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+impl ManualTime {
+    /// Creates a time source stopped at the current time.
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Arc::new(Mutex::new(Duration::ZERO)),
+        }
+    }
+
+    /// Moves the time of this source and all its clones forward. The time
+    /// stops at the latest `Instant` the platform can represent.
+    ///
+    /// # Arguments
+    /// * `by` - How far to move the time.
+    pub fn advance(&self, by: Duration) {
+        let mut elapsed = self.elapsed.lock().unwrap();
+        let (mut remaining, mut step) = (by, by);
+        while !remaining.is_zero() && !step.is_zero() {
+            step = step.min(remaining);
+            match elapsed
+                .checked_add(step)
+                .filter(|&total| self.start.checked_add(total).is_some())
+            {
+                Some(total) => {
+                    *elapsed = total;
+                    remaining -= step;
+                }
+                None => step /= 2,
+            }
+        }
+    }
+}
+
+impl Default for ManualTime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeSource for ManualTime {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ManualTime, TimeSource};
+    use std::time::Duration;
+
+    /// Tests that clones of a manual time source move together.
+    #[test]
+    fn manual_time() {
+        let time = ManualTime::new();
+        let clone = time.clone();
+        let start = time.now();
+        assert_eq!(clone.now(), start);
+
+        clone.advance(Duration::from_secs(5));
+        assert_eq!(time.now() - start, Duration::from_secs(5));
+    }
+
+    /// Tests that a large advance is not truncated, and that advancing past
+    /// the range of `Instant` stops the time instead of panicking.
+    #[test]
+    fn large_advance() {
+        let time = ManualTime::new();
+        let start = time.now();
+        time.advance(Duration::from_secs(1 << 40));
+        assert_eq!(time.now() - start, Duration::from_secs(1 << 40));
+
+        time.advance(Duration::MAX);
+        let end = time.now();
+        assert!(end > start + Duration::from_secs(1 << 40));
+        time.advance(Duration::from_secs(1));
+        assert_eq!(time.now(), end);
+    }
+}
+
+/* time.rs ends here */
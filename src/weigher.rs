@@ -0,0 +1,44 @@
+/* weigher.rs --- WEIGHER
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+/// Measures how much of a table's weight budget an entry uses, for instance
+/// the number of bytes its value holds.
+///
+/// Any `Fn(&K, &V) -> u32` closure is a weigher.
+pub trait Weigher<K, V> {
+    /// Returns the weight of the entry made of `key` and `value`.
+    fn weight(&self, key: &K, value: &V) -> u32;
+}
+
+impl<K, V, F: Fn(&K, &V) -> u32> Weigher<K, V> for F {
+    fn weight(&self, key: &K, value: &V) -> u32 {
+        self(key, value)
+    }
+}
+
+/// A weigher together with the total weight the live entries of a table
+/// may add up to.
+pub(crate) struct Budget<K, V> {
+    pub(crate) weigher: Box<dyn Weigher<K, V> + Send>,
+    pub(crate) max_weight: u64,
+}
+
+/* weigher.rs ends here */
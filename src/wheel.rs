@@ -0,0 +1,300 @@
+/* wheel.rs --- WHEEL
+
+*
+* Author: M.R.Siavash Katebzadeh <mr@katebzadeh.xyz>
+* Keywords: Rust
+* Version: 0.0.1
+*
+* This program is free software; you can redistribute it and/or modify
+* it under the terms of the GNU General Public License as published by
+* the Free Software Foundation, either version 3 of the License, or
+* (at your option) any later version.
+*
+* This program is distributed in the hope that it will be useful,
+* but WITHOUT ANY WARRANTY; without even the implied warranty of
+* MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+* GNU General Public License for more details.
+*
+* You should have received a copy of the GNU General Public License
+* along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::time::{Duration, Instant};
+
+/// The number of slots per level, and the factor between the spans of the
+/// slots of two consecutive levels.
+const SLOTS: usize = 64;
+/// The number of bits of a tick that pick the slot within a level.
+const SLOT_BITS: u32 = SLOTS.trailing_zeros();
+/// The number of levels. Together they span 2^36 ticks; later deadlines wait
+/// in an overflow list until the wheel gets that far.
+const LEVELS: usize = 6;
+/// The bucket of the timers due after the current turn of the wheel, which
+/// follows the slots of every level.
+const OVERFLOW: usize = LEVELS * SLOTS;
+
+/// The deadline of the entry at a log position, as scheduled in the wheel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Timer {
+    pub(crate) pos: usize,
+    pub(crate) deadline: Instant,
+    tick: u64,
+}
+
+/// A hierarchical timing wheel of entry deadlines.
+///
+/// Time is cut into ticks of a fixed resolution, counted from `start`. Level
+/// 0 has one slot per tick, and every slot of level `l` spans 64 slots of
+/// level `l - 1`. A timer goes into the lowest level whose slot tells its
+/// tick apart from the current one; when the wheel reaches a slot of a higher
+/// level, its timers cascade down. Each level keeps a bitmask of its
+/// non-empty slots, so `advance` jumps straight to the next one and its cost
+/// follows the number of timers, not the time elapsed or the log size.
+///
+/// A log position has at most one timer: scheduling it again replaces the
+/// timer, and `cancel` drops it in constant time, so the wheel never holds
+/// more timers than the log has positions.
+pub(crate) struct TimingWheel {
+    start: Instant,
+    resolution: Duration,
+    current: u64,
+    buckets: Box<[Vec<Timer>]>,
+    occupied: [u64; LEVELS],
+    placed: Box<[Option<(usize, usize)>]>,
+}
+
+impl TimingWheel {
+    /// Creates an empty wheel whose tick 0 is `start`, for the timers of a
+    /// log of `positions` entries.
+    ///
+    /// # Panics
+    /// If `resolution` is zero.
+    pub(crate) fn new(start: Instant, resolution: Duration, positions: usize) -> Self {
+        assert!(!resolution.is_zero(), "the resolution must not be zero");
+        Self {
+            start,
+            resolution,
+            current: 0,
+            buckets: (0..=OVERFLOW).map(|_| Vec::new()).collect(),
+            occupied: [0; LEVELS],
+            placed: vec![None; positions].into_boxed_slice(),
+        }
+    }
+
+    /// Returns the turn of the whole wheel that `tick` falls in.
+    #[inline]
+    fn epoch(tick: u64) -> u64 {
+        tick >> (SLOT_BITS * LEVELS as u32)
+    }
+
+    /// Returns the number of whole ticks from `start` to `time`, rounded up
+    /// if `up` is set. Times before `start` are tick 0.
+    fn ticks(&self, time: Instant, up: bool) -> u64 {
+        let elapsed = time.saturating_duration_since(self.start).as_nanos();
+        let resolution = self.resolution.as_nanos();
+        let ticks = if up {
+            elapsed.div_ceil(resolution)
+        } else {
+            elapsed / resolution
+        };
+        ticks.min(u64::MAX as u128) as u64
+    }
+
+    /// Schedules the entry at log position `pos` to expire at `deadline`,
+    /// replacing the timer of the position, if any.
+    pub(crate) fn schedule(&mut self, pos: usize, deadline: Instant) {
+        self.cancel(pos);
+        let tick = self.ticks(deadline, true).max(self.current);
+        self.place(Timer {
+            pos,
+            deadline,
+            tick,
+        });
+    }
+
+    /// Drops the timer of log position `pos`, if any.
+    pub(crate) fn cancel(&mut self, pos: usize) {
+        let Some((bucket, index)) = self.placed[pos].take() else {
+            return;
+        };
+        let timers = &mut self.buckets[bucket];
+        timers.swap_remove(index);
+        if let Some(moved) = timers.get(index) {
+            self.placed[moved.pos] = Some((bucket, index));
+        }
+        if timers.is_empty() && bucket != OVERFLOW {
+            self.occupied[bucket / SLOTS] &= !(1 << (bucket % SLOTS));
+        }
+    }
+
+    /// Puts a timer due at or after the current tick into its slot, or into
+    /// the overflow list if it is due after the current turn of the wheel.
+    fn place(&mut self, timer: Timer) {
+        let bucket = if Self::epoch(timer.tick) != Self::epoch(self.current) {
+            OVERFLOW
+        } else {
+            let differing = (timer.tick ^ self.current) | (SLOTS as u64 - 1);
+            let level = ((63 - differing.leading_zeros()) / SLOT_BITS) as usize;
+            let slot = (timer.tick >> (SLOT_BITS * level as u32)) as usize % SLOTS;
+            self.occupied[level] |= 1 << slot;
+            level * SLOTS + slot
+        };
+        self.placed[timer.pos] = Some((bucket, self.buckets[bucket].len()));
+        self.buckets[bucket].push(timer);
+    }
+
+    /// Empties `bucket`, pushing its timers due by `target` to `fired` and
+    /// placing the others again.
+    fn drain(&mut self, bucket: usize, target: u64, fired: &mut Vec<Timer>) {
+        for timer in std::mem::take(&mut self.buckets[bucket]) {
+            self.placed[timer.pos] = None;
+            if timer.tick <= target {
+                fired.push(timer);
+            } else {
+                self.place(timer);
+            }
+        }
+    }
+
+    /// Returns the level, slot and first tick of the non-empty slot the wheel
+    /// reaches next, if any.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        (0..LEVELS)
+            .filter(|&level| self.occupied[level] != 0)
+            .map(|level| {
+                let shift = SLOT_BITS * level as u32;
+                let now_slot = (self.current >> shift) as usize % SLOTS;
+                let ahead = self.occupied[level]
+                    .rotate_right(now_slot as u32)
+                    .trailing_zeros() as u64;
+                let level_start = (self.current >> shift >> SLOT_BITS) << SLOT_BITS << shift;
+                let first = level_start + ((now_slot as u64 + ahead) << shift);
+                (level, (now_slot + ahead as usize) % SLOTS, first)
+            })
+            .min_by_key(|&(_, _, first)| first)
+    }
+
+    /// Moves the wheel to `now` and pushes every timer due by then to
+    /// `fired`, cascading the timers of the higher-level slots it reaches.
+    pub(crate) fn advance(&mut self, now: Instant, fired: &mut Vec<Timer>) {
+        let target = self.ticks(now, false).max(self.current);
+        let epoch = Self::epoch(self.current);
+        while let Some((level, slot, first)) = self.next_slot() {
+            if first > target {
+                break;
+            }
+            self.current = first;
+            self.occupied[level] &= !(1 << slot);
+            self.drain(level * SLOTS + slot, target, fired);
+        }
+        self.current = target;
+        if Self::epoch(target) != epoch {
+            self.drain(OVERFLOW, target, fired);
+        }
+    }
+
+    /// Drops every timer.
+    pub(crate) fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(Vec::clear);
+        self.occupied = [0; LEVELS];
+        self.placed.iter_mut().for_each(|placed| *placed = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Timer, TimingWheel};
+    use std::time::{Duration, Instant};
+
+    /// Collects the log positions of the timers due by `now`.
+    fn fire(wheel: &mut TimingWheel, now: Instant) -> Vec<usize> {
+        let mut fired = Vec::new();
+        wheel.advance(now, &mut fired);
+        let mut positions: Vec<_> = fired.iter().map(|timer: &Timer| timer.pos).collect();
+        positions.sort();
+        positions
+    }
+
+    /// Tests that timers fire once their deadline has passed, across levels.
+    #[test]
+    fn fires_in_order() {
+        let start = Instant::now();
+        let ms = Duration::from_millis(1);
+        let mut wheel = TimingWheel::new(start, ms, 4);
+        wheel.schedule(0, start + ms * 3);
+        wheel.schedule(1, start + ms * 70);
+        wheel.schedule(2, start + ms * 5000);
+        wheel.schedule(3, start + ms * 300_000);
+
+        assert_eq!(fire(&mut wheel, start + ms * 2), Vec::<usize>::new());
+        assert_eq!(fire(&mut wheel, start + ms * 3), vec![0]);
+        assert_eq!(fire(&mut wheel, start + ms * 69), Vec::<usize>::new());
+        assert_eq!(fire(&mut wheel, start + ms * 4999), vec![1]);
+        assert_eq!(fire(&mut wheel, start + ms * 5000), vec![2]);
+        assert_eq!(fire(&mut wheel, start + ms * 299_999), Vec::<usize>::new());
+        assert_eq!(fire(&mut wheel, start + ms * 400_000), vec![3]);
+    }
+
+    /// Tests that deadlines beyond the span of the wheel wait in the
+    /// overflow list and still fire on time.
+    #[test]
+    fn overflows() {
+        let start = Instant::now();
+        let mut wheel = TimingWheel::new(start, Duration::from_nanos(1), 2);
+        let far = Duration::from_nanos(1 << 37);
+        wheel.schedule(0, start + far);
+        wheel.schedule(1, start + Duration::from_nanos(10));
+
+        let just_before = far - Duration::from_nanos(1);
+        assert_eq!(fire(&mut wheel, start + just_before), vec![1]);
+        assert_eq!(fire(&mut wheel, start + far), vec![0]);
+    }
+
+    /// Tests that rescheduling a position replaces its timer, and that a
+    /// cancelled timer never fires, wherever it sits in the wheel.
+    #[test]
+    fn one_timer_per_position() {
+        let start = Instant::now();
+        let ms = Duration::from_millis(1);
+        let mut wheel = TimingWheel::new(start, ms, 3);
+        for round in 1..1000 {
+            wheel.schedule(0, start + ms * round);
+            wheel.schedule(1, start + ms * 5000 * round);
+        }
+        wheel.schedule(2, start + ms * 10);
+        wheel.schedule(2, start + Duration::from_secs(1 << 40));
+        let timers = wheel.buckets.iter().map(Vec::len).sum::<usize>();
+        assert_eq!(timers, 3);
+
+        wheel.cancel(2);
+        wheel.cancel(2);
+        assert_eq!(fire(&mut wheel, start + ms * 998), Vec::<usize>::new());
+        assert_eq!(fire(&mut wheel, start + ms * 999), vec![0]);
+        assert_eq!(
+            fire(&mut wheel, start + Duration::from_secs(1 << 41)),
+            vec![1]
+        );
+        assert!(wheel.placed.iter().all(Option::is_none));
+    }
+
+    /// Tests that a deadline between ticks fires on the next tick, and that
+    /// past deadlines fire on the next advance.
+    #[test]
+    fn rounds_up() {
+        let start = Instant::now();
+        let ms = Duration::from_millis(1);
+        let mut wheel = TimingWheel::new(start, ms * 10, 3);
+        wheel.schedule(0, start + ms * 15);
+        assert_eq!(fire(&mut wheel, start + ms * 19), Vec::<usize>::new());
+        assert_eq!(fire(&mut wheel, start + ms * 20), vec![0]);
+
+        wheel.schedule(1, start);
+        assert_eq!(fire(&mut wheel, start + ms * 20), vec![1]);
+
+        wheel.schedule(2, start + ms * 100);
+        wheel.clear();
+        assert_eq!(fire(&mut wheel, start + ms * 100), Vec::<usize>::new());
+    }
+}
+
+/* wheel.rs ends here */